@@ -0,0 +1,94 @@
+//! Fan-out of one broadcast to every subscriber of a large room.
+//!
+//! Target: a broadcast to 50k subscribers encodes a single frame and returns in
+//! under 50ms on a release build. Run with `cargo bench -p axum-ws --bench fanout`.
+
+use axum_ws::{
+    Channel, OverflowPolicy, Payload, Socket, Topic, Transport, TransportMessage, TransportSink,
+    TransportStream, WebSocket,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use futures::{stream, SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::{convert::Infallible, io, sync::Arc};
+
+const ROOMS: [usize; 3] = [1_000, 10_000, 50_000];
+
+#[derive(Default)]
+struct Bench;
+
+/// A client that joins `topic` and discards everything it is sent.
+struct Subscriber {
+    topic: String,
+}
+
+impl Transport for Subscriber {
+    type Error = io::Error;
+
+    fn split(self) -> (TransportSink<Self::Error>, TransportStream<Self::Error>) {
+        let join = json!(["1", "1", self.topic, "phx_join", {}]).to_string();
+        let sink = futures::sink::drain().sink_map_err(|never: Infallible| match never {});
+        let stream = stream::once(async { Ok(TransportMessage::Text(join)) })
+            .chain(stream::pending())
+            .boxed();
+
+        (Box::pin(sink), stream)
+    }
+}
+
+async fn join(_topic: Topic, _payload: Payload, _socket: Socket) -> anyhow::Result<Value> {
+    Ok(json!({}))
+}
+
+async fn subscribe(websocket: &Arc<WebSocket<Bench>>, topic: &str, subscribers: usize) {
+    for _ in 0..subscribers {
+        let accepted = websocket.clone().accept(json!({})).await.unwrap();
+        let topic = topic.to_string();
+        tokio::spawn(accepted.serve(Subscriber { topic }));
+    }
+
+    // Joins are handled by each connection's own task.
+    loop {
+        let report = WebSocket::<Bench>::broadcast(topic, "ready", Ok(json!({}))).await;
+
+        if report.unwrap().targeted == subscribers {
+            break;
+        }
+
+        tokio::task::yield_now().await;
+    }
+}
+
+fn fan_out(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let websocket = Arc::new(
+        WebSocket::<Bench>::new("/bench")
+            .overflow_policy(OverflowPolicy::DropOldest)
+            .channel("room:*", Channel::new().join(join)),
+    );
+    let payload = json!({"body": "x".repeat(256)});
+    let mut group = c.benchmark_group("broadcast");
+
+    for subscribers in ROOMS {
+        let topic = format!("room:{subscribers}");
+        runtime.block_on(subscribe(&websocket, &topic, subscribers));
+
+        group.throughput(Throughput::Elements(subscribers as u64));
+        group.bench_function(BenchmarkId::from_parameter(subscribers), |b| {
+            b.iter(|| {
+                runtime
+                    .block_on(WebSocket::<Bench>::broadcast(
+                        &topic,
+                        "new_msg",
+                        Ok(payload.clone()),
+                    ))
+                    .unwrap()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, fan_out);
+criterion_main!(benches);
@@ -1,10 +1,18 @@
-use std::{any::Any, collections::HashMap, fmt};
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt,
+};
 
 type AnyMap = HashMap<String, Box<dyn AnyClone + Send + Sync>>;
+type TypeMap = HashMap<TypeId, Box<dyn AnyClone + Send + Sync>>;
 
+/// Loosely typed data of a socket, keyed by string or, like `http::Extensions`,
+/// by type so that libraries can attach their own without key collisions.
 #[derive(Clone, Default)]
 pub struct Assigns {
     map: Option<Box<AnyMap>>,
+    typed: Option<Box<TypeMap>>,
 }
 
 impl Assigns {
@@ -101,21 +109,94 @@ impl Assigns {
             .and_then(|boxed| boxed.into_any().downcast().ok().map(|boxed| *boxed))
     }
 
+    /// Insert a value keyed by its type, returning the previous one.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use axum_ws::Assigns;
+    /// #[derive(Clone, Debug, PartialEq)]
+    /// struct TenantId(u64);
+    ///
+    /// let mut assigns = Assigns::new();
+    /// assert!(assigns.insert_typed(TenantId(7)).is_none());
+    /// assert_eq!(assigns.insert_typed(TenantId(8)), Some(TenantId(7)));
+    /// ```
+    pub fn insert_typed<T>(&mut self, val: T) -> Option<T>
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        self.typed
+            .get_or_insert_with(Box::default)
+            .insert(TypeId::of::<T>(), Box::new(val))
+            .and_then(|boxed| boxed.into_any().downcast().ok().map(|boxed| *boxed))
+    }
+
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use axum_ws::Assigns;
+    /// #[derive(Clone, Debug, PartialEq)]
+    /// struct TenantId(u64);
+    ///
+    /// let mut assigns = Assigns::new();
+    /// assert!(assigns.get_typed::<TenantId>().is_none());
+    /// assigns.insert_typed(TenantId(7));
+    ///
+    /// assert_eq!(assigns.get_typed::<TenantId>(), Some(&TenantId(7)));
+    /// ```
+    pub fn get_typed<T>(&self) -> Option<&T>
+    where
+        T: Send + Sync + 'static,
+    {
+        self.typed
+            .as_ref()
+            .and_then(|map| map.get(&TypeId::of::<T>()))
+            .and_then(|boxed| (**boxed).as_any().downcast_ref())
+    }
+
+    pub fn get_typed_mut<T>(&mut self) -> Option<&mut T>
+    where
+        T: Send + Sync + 'static,
+    {
+        self.typed
+            .as_mut()
+            .and_then(|map| map.get_mut(&TypeId::of::<T>()))
+            .and_then(|boxed| (**boxed).as_any_mut().downcast_mut())
+    }
+
+    pub fn remove_typed<T>(&mut self) -> Option<T>
+    where
+        T: Send + Sync + 'static,
+    {
+        self.typed
+            .as_mut()
+            .and_then(|map| map.remove(&TypeId::of::<T>()))
+            .and_then(|boxed| boxed.into_any().downcast().ok().map(|boxed| *boxed))
+    }
+
     #[inline]
     pub fn clear(&mut self) {
         if let Some(ref mut map) = self.map {
             map.clear();
         }
+
+        if let Some(ref mut typed) = self.typed {
+            typed.clear();
+        }
     }
 
     #[inline]
     pub fn is_empty(&self) -> bool {
-        self.map.as_ref().map_or(true, |map| map.is_empty())
+        self.len() == 0
     }
 
+    /// Number of values under string keys and types together.
     #[inline]
     pub fn len(&self) -> usize {
         self.map.as_ref().map_or(0, |map| map.len())
+            + self.typed.as_ref().map_or(0, |typed| typed.len())
     }
 
     pub fn extend(&mut self, other: Self) {
@@ -126,17 +207,65 @@ impl Assigns {
                 self.map = Some(other);
             }
         }
+
+        if let Some(other) = other.typed {
+            if let Some(typed) = &mut self.typed {
+                typed.extend(*other);
+            } else {
+                self.typed = Some(other);
+            }
+        }
     }
 }
 
+/// Lists the string keys with the type names of their values, then the types
+/// of the typed values, the values themselves not being `Debug`.
 impl fmt::Debug for Assigns {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("Assigns").finish()
+        let mut keys = self
+            .map
+            .iter()
+            .flat_map(|map| map.iter())
+            .map(|(key, value)| (key.as_str(), TypeName((**value).type_name())))
+            .collect::<Vec<_>>();
+        keys.sort_by_key(|(key, _)| *key);
+
+        let mut types = self
+            .typed
+            .iter()
+            .flat_map(|typed| typed.values())
+            .map(|value| TypeName((**value).type_name()))
+            .collect::<Vec<_>>();
+        types.sort_by_key(|name| name.0);
+
+        f.debug_struct("Assigns")
+            .field("keys", &DebugMap(keys))
+            .field("types", &types)
+            .finish()
+    }
+}
+
+struct TypeName(&'static str);
+
+impl fmt::Debug for TypeName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+struct DebugMap<'a>(Vec<(&'a str, TypeName)>);
+
+impl fmt::Debug for DebugMap<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.0.iter().map(|(key, name)| (key, name)))
+            .finish()
     }
 }
 
 pub(crate) trait AnyClone: Any {
     fn clone_box(&self) -> Box<dyn AnyClone + Send + Sync>;
+    fn type_name(&self) -> &'static str;
     fn as_any(&self) -> &dyn Any;
     fn as_any_mut(&mut self) -> &mut dyn Any;
     fn into_any(self: Box<Self>) -> Box<dyn Any>;
@@ -147,6 +276,10 @@ impl<T: Clone + Send + Sync + 'static> AnyClone for T {
         Box::new(self.clone())
     }
 
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<T>()
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -191,3 +324,32 @@ fn test_assigns() {
     assert_eq!(assigns.get::<bool>("my_key3"), None);
     assert_eq!(assigns.get("my_key2"), Some(&MyType(10)));
 }
+
+#[test]
+fn test_typed_assigns() {
+    #[derive(Clone, Debug, PartialEq)]
+    struct CurrentUser(&'static str);
+    #[derive(Clone, Debug, PartialEq)]
+    struct TenantId(u64);
+
+    let mut assigns = Assigns::new();
+
+    assigns.insert("user_id", 1i64);
+    assigns.insert_typed(CurrentUser("alice"));
+    assigns.insert_typed(TenantId(7));
+    assert_eq!(assigns.len(), 3);
+
+    // string and typed keys never collide
+    assert_eq!(assigns.get_typed::<i64>(), None);
+    assert_eq!(assigns.get::<TenantId>("TenantId"), None);
+
+    assigns.get_typed_mut::<TenantId>().unwrap().0 += 1;
+    assert_eq!(assigns.get_typed(), Some(&TenantId(8)));
+
+    let debug = format!("{assigns:?}");
+    assert!(debug.starts_with(r#"Assigns { keys: {"user_id": i64}, types: ["#));
+    assert!(debug.contains("CurrentUser"));
+
+    assert_eq!(assigns.remove_typed(), Some(CurrentUser("alice")));
+    assert!(assigns.get_typed::<CurrentUser>().is_none());
+}
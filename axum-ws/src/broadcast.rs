@@ -0,0 +1,73 @@
+use crate::{outbound::Offer, socket};
+use serde::Serialize;
+
+/// What became of a broadcast on this node, subscriber by subscriber.
+///
+/// A failure to reach one subscriber never stops the others from getting the
+/// message, so the report is the only place it shows up.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct BroadcastReport {
+    /// Subscribers the message was addressed to, after exclusions.
+    pub targeted: usize,
+    /// Subscribers whose outbound queue took the message. Relayed broadcasts
+    /// count once queued for the relaying connection, before `intercept` runs.
+    pub delivered: usize,
+    /// Subscribers whose queue was full, under a policy that discards the
+    /// message or closes the connection.
+    pub dropped: usize,
+    /// Subscribers whose connection was already closed.
+    pub failed: usize,
+}
+
+impl BroadcastReport {
+    pub(crate) fn record(&mut self, offer: Offer) {
+        match offer {
+            Offer::Queued | Offer::Replaced => self.delivered += 1,
+            Offer::Dropped | Offer::Overflowed => self.dropped += 1,
+            Offer::Closed => self.failed += 1,
+            Offer::Full => {}
+        }
+    }
+
+    /// Add up the report of another fan-out of the same broadcast.
+    pub(crate) fn merge(&mut self, other: BroadcastReport) {
+        self.targeted += other.targeted;
+        self.delivered += other.delivered;
+        self.dropped += other.dropped;
+        self.failed += other.failed;
+    }
+}
+
+/// A predicate a subscriber's socket must satisfy to get a broadcast.
+pub(crate) type Predicate<'a> = &'a (dyn Fn(&socket::Socket) -> bool + Send + Sync);
+
+/// Who among a topic's subscribers a broadcast is for.
+#[derive(Default, Clone, Copy)]
+pub(crate) struct Audience<'a> {
+    /// Socket ids whose connections are skipped.
+    pub(crate) exclude: &'a [&'a str],
+    pub(crate) predicate: Option<Predicate<'a>>,
+    /// The socket broadcasting, whose lock its caller may be holding.
+    pub(crate) sender: Option<&'a socket::Socket>,
+}
+
+impl<'a> Audience<'a> {
+    pub(crate) fn except(exclude: &'a [&'a str]) -> Self {
+        Self {
+            exclude,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn filter(predicate: Predicate<'a>) -> Self {
+        Self {
+            predicate: Some(predicate),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn sent_by(mut self, sender: &'a socket::Socket) -> Self {
+        self.sender = Some(sender);
+        self
+    }
+}
@@ -1,11 +1,16 @@
 use crate::{
-    handler::{Handler, HandlerWrapper, IntoResponse, Join, JoinWrapper},
+    handler::{
+        HandleIn, HandleInWrapper, Handler, HandlerWrapper, Info, InfoWrapper, Intercept,
+        InterceptWrapper, IntoResponse, Join, JoinWrapper, Terminate, TerminateWrapper,
+    },
     payload::Payload,
     topic::Topic,
+    topic_params::TopicParams,
     Socket,
 };
 use anyhow::Result;
 use futures::Future;
+use serde::de::DeserializeOwned;
 use serde_json::Value;
 use std::collections::HashMap;
 
@@ -13,6 +18,10 @@ use std::collections::HashMap;
 pub struct Channel {
     pub(crate) join: Option<Box<dyn Join + Send + Sync>>,
     pub(crate) handler: HashMap<String, Box<dyn Handler + Send + Sync>>,
+    pub(crate) terminate: Option<Box<dyn Terminate + Send + Sync>>,
+    pub(crate) intercept: Option<Box<dyn Intercept + Send + Sync>>,
+    pub(crate) handle_in: Option<Box<dyn HandleIn + Send + Sync>>,
+    pub(crate) info: Option<Box<dyn Info + Send + Sync>>,
 }
 
 impl Channel {
@@ -20,6 +29,10 @@ impl Channel {
         Self {
             join: None,
             handler: HashMap::new(),
+            terminate: None,
+            intercept: None,
+            handle_in: None,
+            info: None,
         }
     }
 
@@ -43,6 +56,91 @@ impl Channel {
         self
     }
 
+    /// Like [`Channel::join`], with the topic params deserialized into `P` as an
+    /// argument, the way axum hands out `Path`. Topics whose params do not fit `P`
+    /// are refused.
+    pub fn join_with_params<P, F, Fut, Res>(self, join: F) -> Self
+    where
+        P: DeserializeOwned + Send + 'static,
+        F: Fn(Topic, TopicParams<P>, Payload, Socket) -> Fut + Clone + Send + Sync + 'static,
+        Fut: Future<Output = Result<Res>> + Send + 'static,
+        Res: Into<Value>,
+    {
+        self.join(move |topic, payload, socket: Socket| {
+            let join = join.clone();
+            async move {
+                let params = socket.lock().await.topic_params::<P>()?;
+                join(topic, params, payload, socket).await
+            }
+        })
+    }
+
+    /// Called with the channel's socket when the client leaves the topic, the
+    /// connection closes or the endpoint shuts down.
+    pub fn terminate<F, Fut>(mut self, terminate: F) -> Self
+    where
+        F: Fn(Topic, Socket) -> Fut + Clone + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.terminate = Some(Box::new(TerminateWrapper::new(move |topic, socket| {
+            let terminate = terminate.clone();
+            Box::pin(async move { terminate(topic, socket).await })
+        })) as Box<dyn Terminate + Send + Sync>);
+        self
+    }
+
+    /// Called with the source topic, event, payload and channel socket for every
+    /// broadcast relayed to the channel from a topic added with `Socket::subscribe`.
+    /// The payload it returns is pushed on the channel's topic, and `None` skips
+    /// the broadcast. It runs on the subscribing connection's relay queue, one
+    /// broadcast at a time, so the broadcaster never waits on it.
+    pub fn intercept<F, Fut>(mut self, intercept: F) -> Self
+    where
+        F: Fn(Topic, String, Payload, Socket) -> Fut + Clone + Send + Sync + 'static,
+        Fut: Future<Output = Option<Value>> + Send + 'static,
+    {
+        self.intercept = Some(Box::new(InterceptWrapper::new(
+            move |topic, event, payload, socket| {
+                let intercept = intercept.clone();
+                Box::pin(async move { intercept(topic, event, payload, socket).await })
+            },
+        )) as Box<dyn Intercept + Send + Sync>);
+        self
+    }
+
+    /// Called with the event name for client events that have no `handler` of their own.
+    pub fn handle_in<F, Fut, Res>(mut self, handle_in: F) -> Self
+    where
+        F: Fn(String, Payload, Socket) -> Fut + Clone + Send + Sync + 'static,
+        Fut: Future<Output = Res> + Send + 'static,
+        Res: IntoResponse,
+    {
+        self.handle_in = Some(
+            Box::new(HandleInWrapper::new(move |event, payload, socket| {
+                let handle_in = handle_in.clone();
+                Box::pin(async move {
+                    let res = handle_in(event, payload, socket).await;
+                    res.into_response()
+                })
+            })) as Box<dyn HandleIn + Send + Sync>,
+        );
+        self
+    }
+
+    /// Called with the messages sent to the channel from the server with
+    /// [`WebSocket::send_info`](crate::WebSocket::send_info).
+    pub fn info<F, Fut>(mut self, info: F) -> Self
+    where
+        F: Fn(Value, Socket) -> Fut + Clone + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.info = Some(Box::new(InfoWrapper::new(move |message, socket| {
+            let info = info.clone();
+            Box::pin(async move { info(message, socket).await })
+        })) as Box<dyn Info + Send + Sync>);
+        self
+    }
+
     pub fn handler<F, Fut, Res>(mut self, event: impl Into<String>, handler: F) -> Self
     where
         F: Fn(Payload, Socket) -> Fut + Clone + Send + Sync + 'static,
@@ -61,6 +159,28 @@ impl Channel {
         self.handler.insert(event, handler);
         self
     }
+
+    /// Like [`Channel::handler`], with the topic params deserialized into `P` as an
+    /// argument. Events on topics whose params do not fit `P` get an error reply.
+    pub fn handler_with_params<P, F, Fut, Res>(self, event: impl Into<String>, handler: F) -> Self
+    where
+        P: DeserializeOwned + Send + 'static,
+        F: Fn(TopicParams<P>, Payload, Socket) -> Fut + Clone + Send + Sync + 'static,
+        Fut: Future<Output = Res> + Send + 'static,
+        Res: IntoResponse,
+    {
+        self.handler(event, move |payload, socket: Socket| {
+            let handler = handler.clone();
+            async move {
+                let params = socket.lock().await.topic_params::<P>();
+
+                match params {
+                    Ok(params) => handler(params, payload, socket).await.into_response(),
+                    Err(err) => Err::<Value, _>(err).into_response(),
+                }
+            }
+        })
+    }
 }
 
 #[cfg(test)]
@@ -94,7 +214,7 @@ mod tests {
         join.call(Topic::default(), Payload::default(), socket.clone())
             .await
             .unwrap();
-        // assert_eq!(socket.assigns.get("test"), Some(&1));
+        assert_eq!(socket.lock().await.assigns.get::<i32>("test"), Some(&1));
 
         let event1 = channel.handler.get("event1").unwrap();
         let response = event1.call(Payload::default(), Socket::default()).await;
@@ -108,4 +228,115 @@ mod tests {
         let response = event1.call(Payload::default(), Socket::default()).await;
         assert_eq!(response, Response::NoReply);
     }
+
+    #[tokio::test]
+    async fn subscribed_topics_should_be_relayed_to_the_channel() {
+        use crate::{test_util::ChannelTest, WebSocket};
+        use serde_json::json;
+        use std::time::Duration;
+
+        #[derive(Default)]
+        struct Endpoint;
+
+        async fn join(_topic: Topic, _payload: Payload, socket: Socket) -> Result<Value> {
+            socket.lock().await.subscribe("user:7:notifications")?;
+            Ok(json!({}))
+        }
+
+        async fn intercept(
+            topic: Topic,
+            event: String,
+            _payload: Payload,
+            _socket: Socket,
+        ) -> Option<Value> {
+            (event != "muted").then(|| json!({"from": &*topic}))
+        }
+
+        async fn mute(_payload: Payload, socket: Socket) {
+            let socket = socket.lock().await;
+            assert!(socket.unsubscribe("user:7:notifications").unwrap());
+        }
+
+        let websocket = WebSocket::<Endpoint>::new("/relay").channel(
+            "room:*",
+            Channel::new()
+                .join(join)
+                .intercept(intercept)
+                .handler("mute", mute),
+        );
+        let test = ChannelTest::new(websocket);
+        let socket = test.connect(json!({})).await.unwrap();
+        let (_, channel) = socket
+            .subscribe_and_join("room:42", json!({}))
+            .await
+            .unwrap();
+
+        let topic = "user:7:notifications";
+        let report = WebSocket::<Endpoint>::broadcast(topic, "new", Ok(json!({})));
+        assert_eq!(report.await.unwrap().delivered, 1);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(channel.assert_push("new"), json!({"from": topic}));
+
+        let report = WebSocket::<Endpoint>::broadcast(topic, "muted", Ok(json!({})));
+        assert_eq!(report.await.unwrap().delivered, 1);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        channel.refute_push("muted");
+
+        channel.push("mute", json!({})).await;
+        let report = WebSocket::<Endpoint>::broadcast(topic, "new", Ok(json!({})));
+        assert_eq!(report.await.unwrap().targeted, 0);
+        channel.refute_push("new");
+    }
+    #[tokio::test]
+    async fn handlers_should_take_topic_params_as_arguments() {
+        use crate::{test_util::ChannelTest, WebSocket};
+        use serde::Deserialize;
+        use serde_json::json;
+
+        #[derive(Default)]
+        struct Endpoint;
+
+        #[derive(Deserialize)]
+        struct RoomParams {
+            room_id: u64,
+        }
+
+        async fn join(
+            _topic: Topic,
+            TopicParams(params): TopicParams<RoomParams>,
+            _payload: Payload,
+            _socket: Socket,
+        ) -> Result<Value> {
+            Ok(json!({"room_id": params.room_id}))
+        }
+
+        async fn whereami(
+            TopicParams(room_id): TopicParams<u64>,
+            _payload: Payload,
+            _socket: Socket,
+        ) -> Result<Value> {
+            Ok(json!({"room_id": room_id}))
+        }
+
+        let websocket = WebSocket::<Endpoint>::new("/topic_params").channel(
+            "room:{room_id}",
+            Channel::new()
+                .join_with_params(join)
+                .handler_with_params("whereami", whereami),
+        );
+        let test = ChannelTest::new(websocket);
+        let socket = test.connect(json!({})).await.unwrap();
+
+        let (reply, channel) = socket
+            .subscribe_and_join("room:42", json!({}))
+            .await
+            .unwrap();
+        assert_eq!(reply, json!({"room_id": 42}));
+
+        let reply = channel.push("whereami", json!({})).await;
+        assert_eq!(channel.assert_reply(&reply, "ok"), json!({"room_id": 42}));
+
+        let err = socket.subscribe_and_join("room:lobby", json!({})).await;
+        assert!(err.is_err());
+    }
 }
@@ -0,0 +1,400 @@
+use crate::{
+    channel::Channel,
+    handler::{IntoResponse, Response},
+    payload::Payload,
+    topic::Topic,
+    Socket,
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use dashmap::DashMap;
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// A channel written as a type instead of closures, with one instance per
+/// joined topic holding whatever state the channel needs.
+///
+/// Implementations use [`async_trait`](crate::async_trait) and are registered
+/// with [`ChannelHandler::factory`]:
+///
+/// ```
+/// use axum_ws::{async_trait, Channel, ChannelHandler, Payload, Socket, Topic, WebSocket};
+/// use serde_json::{json, Value};
+///
+/// #[derive(Default)]
+/// struct Room {
+///     messages: usize,
+/// }
+///
+/// #[async_trait]
+/// impl ChannelHandler for Room {
+///     async fn join(&mut self, _topic: Topic, _payload: Payload, _socket: Socket) -> anyhow::Result<Value> {
+///         Ok(json!({}))
+///     }
+///
+///     async fn handle_in(&mut self, event: String, _payload: Payload, _socket: Socket) -> anyhow::Result<Value> {
+///         self.messages += 1;
+///         Ok(json!({"event": event, "messages": self.messages}))
+///     }
+/// }
+///
+/// let websocket = WebSocket::<()>::new("/socket").channel("room:*", Room::factory());
+/// ```
+///
+/// The instance is locked while one of its methods runs. `handle_out` is called
+/// from the connection's relay queue rather than by the broadcaster, so a method
+/// may broadcast to a topic its own channel relays.
+#[async_trait]
+pub trait ChannelHandler: Send + 'static {
+    /// Runs when a client joins, the channel being entered only if it succeeds.
+    async fn join(&mut self, topic: Topic, payload: Payload, socket: Socket) -> Result<Value>;
+
+    /// An event pushed by the client, answered with the value unless it is `null`.
+    async fn handle_in(
+        &mut self,
+        event: String,
+        payload: Payload,
+        socket: Socket,
+    ) -> Result<Value> {
+        let _ = (event, payload, socket);
+        Ok(Value::Null)
+    }
+
+    /// A broadcast relayed from a topic added with `Socket::subscribe`, pushed to
+    /// the client with the returned payload, or skipped on `None`.
+    ///
+    /// Unlike Phoenix's `handle_out`, broadcasts on the channel's own topic go
+    /// straight to the client and never reach this method.
+    async fn handle_out(
+        &mut self,
+        topic: Topic,
+        event: String,
+        payload: Payload,
+        socket: Socket,
+    ) -> Option<Value> {
+        let _ = (topic, event, socket);
+        Some(payload.into())
+    }
+
+    /// A message sent with [`WebSocket::send_info`](crate::WebSocket::send_info).
+    async fn handle_info(&mut self, message: Value, socket: Socket) {
+        let _ = (message, socket);
+    }
+
+    /// Runs when the channel is left, kicked or its connection closes, right
+    /// before the instance is dropped.
+    async fn terminate(&mut self, topic: Topic, socket: Socket) {
+        let _ = (topic, socket);
+    }
+
+    /// A [`Channel`] that creates an instance with `Default` on every join and
+    /// dispatches to it.
+    fn factory() -> Channel
+    where
+        Self: Default + Sized,
+    {
+        let instances = Instances::<Self>::default();
+
+        Channel::new()
+            .join({
+                let instances = instances.clone();
+                move |topic, payload, socket| {
+                    let instances = instances.clone();
+                    async move {
+                        let mut instance = Self::default();
+                        let res = instance.join(topic, payload, socket.clone()).await;
+
+                        if res.is_ok() {
+                            instances.insert(&socket, instance).await;
+                        }
+
+                        res
+                    }
+                }
+            })
+            .handle_in({
+                let instances = instances.clone();
+                move |event, payload, socket| {
+                    let instances = instances.clone();
+                    async move {
+                        let Some(instance) = instances.get(&socket).await else {
+                            return Response::NoReply;
+                        };
+
+                        let mut instance = instance.lock().await;
+                        instance
+                            .handle_in(event, payload, socket)
+                            .await
+                            .into_response()
+                    }
+                }
+            })
+            .intercept({
+                let instances = instances.clone();
+                move |topic, event, payload, socket| {
+                    let instances = instances.clone();
+                    async move {
+                        let instance = instances.get(&socket).await?;
+                        let mut instance = instance.lock().await;
+                        instance.handle_out(topic, event, payload, socket).await
+                    }
+                }
+            })
+            .info({
+                let instances = instances.clone();
+                move |message, socket| {
+                    let instances = instances.clone();
+                    async move {
+                        if let Some(instance) = instances.get(&socket).await {
+                            instance.lock().await.handle_info(message, socket).await;
+                        }
+                    }
+                }
+            })
+            .terminate(move |topic, socket| {
+                let instances = instances.clone();
+                async move {
+                    if let Some(instance) = instances.remove(&socket).await {
+                        instance.lock().await.terminate(topic, socket).await;
+                    }
+                }
+            })
+    }
+}
+
+pub(crate) type Instance<H> = Arc<Mutex<H>>;
+
+/// The live instances of a [`ChannelHandler`] or channel state, by connection and topic.
+pub(crate) struct Instances<H>(Arc<DashMap<(String, Topic), Instance<H>>>);
+
+impl<H> Clone for Instances<H> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<H> Default for Instances<H> {
+    fn default() -> Self {
+        Self(Arc::default())
+    }
+}
+
+impl<H> Instances<H> {
+    pub(crate) async fn insert(&self, socket: &Socket, instance: H) {
+        if let Some(key) = socket.lock().await.channel_key() {
+            self.0.insert(key, Arc::new(Mutex::new(instance)));
+        }
+    }
+
+    pub(crate) async fn get(&self, socket: &Socket) -> Option<Instance<H>> {
+        let key = socket.lock().await.channel_key()?;
+        self.0.get(&key).map(|instance| instance.clone())
+    }
+
+    pub(crate) async fn remove(&self, socket: &Socket) -> Option<Instance<H>> {
+        let key = socket.lock().await.channel_key()?;
+        self.0.remove(&key).map(|(_, instance)| instance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_util::fixture::{self, connect},
+        WebSocket,
+    };
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static TERMINATED: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Default)]
+    struct Endpoint;
+
+    #[derive(Default)]
+    struct Counter {
+        count: u64,
+    }
+
+    #[async_trait]
+    impl ChannelHandler for Counter {
+        async fn join(
+            &mut self,
+            _topic: Topic,
+            payload: Payload,
+            _socket: Socket,
+        ) -> Result<Value> {
+            self.count = Value::from(payload)["start"].as_u64().unwrap_or_default();
+            Ok(json!({}))
+        }
+
+        async fn handle_in(
+            &mut self,
+            event: String,
+            _payload: Payload,
+            _socket: Socket,
+        ) -> Result<Value> {
+            match event.as_str() {
+                "incr" => {
+                    self.count += 1;
+                    Ok(json!({"count": self.count}))
+                }
+                _ => Ok(Value::Null),
+            }
+        }
+
+        async fn handle_info(&mut self, message: Value, socket: Socket) {
+            self.count += message["add"].as_u64().unwrap_or_default();
+            let socket = socket.lock().await;
+            let _ = socket.push("count", Ok(json!({"count": self.count}))).await;
+        }
+
+        async fn terminate(&mut self, _topic: Topic, _socket: Socket) {
+            TERMINATED.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn channel_handler_should_keep_state_per_joined_topic() {
+        let websocket = WebSocket::<Endpoint>::new("/channel_handler")
+            .id(|_socket: Socket| async { Some("counter".to_string()) })
+            .channel("counter:*", Counter::factory());
+        let socket = connect(websocket).await;
+
+        let (_, a) = socket
+            .subscribe_and_join("counter:a", json!({"start": 10}))
+            .await
+            .unwrap();
+        let (_, b) = socket
+            .subscribe_and_join("counter:b", json!({}))
+            .await
+            .unwrap();
+
+        let reply = a.push("incr", json!({})).await;
+        assert_eq!(a.assert_reply(&reply, "ok"), json!({"count": 11}));
+        let reply = b.push("incr", json!({})).await;
+        assert_eq!(b.assert_reply(&reply, "ok"), json!({"count": 1}));
+
+        let sent = WebSocket::<Endpoint>::send_info("counter", "counter:a", json!({"add": 5}));
+        assert_eq!(sent.await, 1);
+        assert_eq!(a.assert_push("count"), json!({"count": 16}));
+
+        a.leave().await;
+        assert_eq!(TERMINATED.load(Ordering::SeqCst), 1);
+        let sent = WebSocket::<Endpoint>::send_info("counter", "counter:a", json!({"add": 5}));
+        assert_eq!(sent.await, 0);
+    }
+
+    #[tokio::test]
+    async fn channel_handler_should_terminate_instances_of_closed_connections() {
+        static CLOSED: AtomicUsize = AtomicUsize::new(0);
+        static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Default)]
+        struct Tracked;
+
+        impl Drop for Tracked {
+            fn drop(&mut self) {
+                DROPPED.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        #[async_trait]
+        impl ChannelHandler for Tracked {
+            async fn join(
+                &mut self,
+                _topic: Topic,
+                _payload: Payload,
+                _socket: Socket,
+            ) -> Result<Value> {
+                Ok(json!({}))
+            }
+
+            async fn terminate(&mut self, _topic: Topic, _socket: Socket) {
+                CLOSED.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let websocket = WebSocket::<fixture::Endpoint>::new("/channel_handler_drop")
+            .channel("tracked:*", Tracked::factory());
+        let socket = connect(websocket).await;
+        let (_, channel) = socket
+            .subscribe_and_join("tracked:1", json!({}))
+            .await
+            .unwrap();
+
+        // The connection goes away without leaving the channel.
+        drop(channel);
+        drop(socket);
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+
+        assert_eq!(CLOSED.load(Ordering::SeqCst), 1);
+        assert_eq!(DROPPED.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn channel_handler_should_broadcast_to_a_topic_it_relays() {
+        use std::time::Duration;
+
+        #[derive(Default)]
+        struct FeedEndpoint;
+
+        #[derive(Default)]
+        struct Feed {
+            relayed: u64,
+        }
+
+        #[async_trait]
+        impl ChannelHandler for Feed {
+            async fn join(
+                &mut self,
+                _topic: Topic,
+                _payload: Payload,
+                socket: Socket,
+            ) -> Result<Value> {
+                socket.lock().await.subscribe("feed:all")?;
+                Ok(json!({}))
+            }
+
+            async fn handle_in(
+                &mut self,
+                event: String,
+                payload: Payload,
+                _socket: Socket,
+            ) -> Result<Value> {
+                WebSocket::<FeedEndpoint>::broadcast("feed:all", &event, Ok(payload.into()))
+                    .await?;
+                Ok(json!({}))
+            }
+
+            async fn handle_out(
+                &mut self,
+                _topic: Topic,
+                _event: String,
+                _payload: Payload,
+                _socket: Socket,
+            ) -> Option<Value> {
+                self.relayed += 1;
+                Some(json!({"relayed": self.relayed}))
+            }
+        }
+
+        let websocket = WebSocket::<FeedEndpoint>::new("/channel_handler_relay")
+            .channel("feed:*", Feed::factory());
+        let socket = connect(websocket).await;
+        let (_, feed) = socket
+            .subscribe_and_join("feed:mine", json!({}))
+            .await
+            .unwrap();
+
+        let reply = tokio::time::timeout(Duration::from_secs(1), feed.push("post", json!({})))
+            .await
+            .expect("handle_in deadlocked on its own relay");
+        feed.assert_reply(&reply, "ok");
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(feed.assert_push("post"), json!({"relayed": 1}));
+    }
+}
@@ -0,0 +1,112 @@
+use super::{Client, ClientError};
+use crate::{event::Event, topic::Topic};
+use futures::Stream;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::sync::mpsc;
+
+/// A message pushed or broadcast by the server on a joined topic.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelEvent {
+    pub event: String,
+    pub payload: Value,
+}
+
+/// A joined topic, and the stream of the events the server sends on it.
+///
+/// The stream ends when the server closes the channel or a rejoin is refused.
+/// Dropping the channel leaves the topic without waiting for the reply.
+pub struct Channel {
+    client: Client,
+    topic: Topic,
+    events: mpsc::UnboundedReceiver<ChannelEvent>,
+    left: bool,
+}
+
+impl Channel {
+    pub(crate) fn new(
+        client: Client,
+        topic: Topic,
+        events: mpsc::UnboundedReceiver<ChannelEvent>,
+    ) -> Self {
+        Self {
+            client,
+            topic,
+            events,
+            left: false,
+        }
+    }
+
+    pub fn topic(&self) -> &Topic {
+        &self.topic
+    }
+
+    /// Response of the latest successful join, updated when the client rejoins.
+    pub fn join_reply<R: DeserializeOwned>(&self) -> Result<R, ClientError> {
+        let reply = self
+            .client
+            .shared
+            .join_reply(&self.topic)
+            .ok_or_else(|| ClientError::NotJoined(self.topic.to_string()))?;
+
+        Ok(serde_json::from_value(reply)?)
+    }
+
+    /// Push an event and wait for the `response` of its reply.
+    ///
+    /// A reply with an `error` status is returned as [`ClientError::Rejected`].
+    pub async fn push<R: DeserializeOwned>(
+        &self,
+        event: &str,
+        payload: impl Serialize,
+    ) -> Result<R, ClientError> {
+        let payload = serde_json::to_value(payload)?;
+        let join_ref = self
+            .client
+            .shared
+            .join_ref(&self.topic)
+            .ok_or_else(|| ClientError::NotJoined(self.topic.to_string()))?;
+
+        let response = self
+            .client
+            .request(&self.topic, event.into(), payload, Some(join_ref))
+            .await?;
+
+        Ok(serde_json::from_value(response)?)
+    }
+
+    pub async fn leave(mut self) -> Result<(), ClientError> {
+        self.left = true;
+
+        let join_ref = self.client.shared.remove_channel(&self.topic);
+        self.client
+            .request(&self.topic, Event::Leave, json!({}), join_ref)
+            .await?;
+
+        Ok(())
+    }
+}
+
+impl Stream for Channel {
+    type Item = ChannelEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().events.poll_recv(cx)
+    }
+}
+
+impl Drop for Channel {
+    fn drop(&mut self) {
+        if self.left {
+            return;
+        }
+
+        let join_ref = self.client.shared.remove_channel(&self.topic);
+        self.client
+            .cast(&self.topic, Event::Leave, json!({}), join_ref);
+    }
+}
@@ -0,0 +1,33 @@
+use serde_json::Value;
+use thiserror::Error;
+use tokio_tungstenite::tungstenite;
+
+#[derive(Error, Debug)]
+pub enum ClientError {
+    #[error("websocket error: {0}")]
+    WebSocketError(Box<tungstenite::Error>),
+
+    #[error("json error: {0}")]
+    JsonSerializeError(#[from] serde_json::Error),
+
+    #[error("request rejected: {0}")]
+    Rejected(Value),
+
+    #[error("request timed out")]
+    Timeout,
+
+    #[error("not connected")]
+    Disconnected,
+
+    #[error("topic already joined: {0}")]
+    AlreadyJoined(String),
+
+    #[error("topic not joined: {0}")]
+    NotJoined(String),
+}
+
+impl From<tungstenite::Error> for ClientError {
+    fn from(err: tungstenite::Error) -> Self {
+        Self::WebSocketError(Box::new(err))
+    }
+}
@@ -0,0 +1,604 @@
+mod channel;
+mod client_error;
+
+pub use channel::{Channel, ChannelEvent};
+pub use client_error::ClientError;
+
+use crate::{event::Event, message::Message, topic::Topic};
+use futures::{SinkExt, StreamExt};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::{
+    net::TcpStream,
+    sync::{mpsc, oneshot},
+    time::Instant,
+};
+use tokio_tungstenite::{tungstenite, MaybeTlsStream, WebSocketStream};
+
+type Stream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+type ReconnectAfter = Arc<dyn Fn(u32) -> Duration + Send + Sync>;
+
+/// Settings of a [`Client`], created by [`Client::builder`].
+#[derive(Clone)]
+pub struct ClientBuilder {
+    endpoint: String,
+    params: Vec<(String, String)>,
+    heartbeat_interval: Duration,
+    timeout: Duration,
+    reconnect_after: ReconnectAfter,
+}
+
+impl ClientBuilder {
+    /// Add a connect param, available to the server's `connect` callback.
+    pub fn param(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.params.push((key.into(), value.into()));
+        self
+    }
+
+    /// Interval of the heartbeats, defaults to 30 seconds.
+    ///
+    /// The connection is considered lost when a heartbeat is still unanswered
+    /// by the time the next one is due.
+    pub fn heartbeat_interval(mut self, heartbeat_interval: Duration) -> Self {
+        self.heartbeat_interval = heartbeat_interval;
+        self
+    }
+
+    /// How long joins and pushes wait for their reply, defaults to 10 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Delay before the given (1-based) reconnect attempt, defaults to the
+    /// phoenix.js schedule going from 10ms up to 5 seconds.
+    pub fn reconnect_after<F>(mut self, reconnect_after: F) -> Self
+    where
+        F: Fn(u32) -> Duration + Send + Sync + 'static,
+    {
+        self.reconnect_after = Arc::new(reconnect_after);
+        self
+    }
+
+    /// Open the connection, failing if the first attempt does not succeed.
+    pub async fn connect(self) -> Result<Client, ClientError> {
+        let url = self.url();
+        let (stream, _) = tokio_tungstenite::connect_async(url.as_str()).await?;
+
+        let shared = Arc::new(Shared::default());
+        shared.connected.store(true, Ordering::Release);
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let connector = Connector {
+            url,
+            heartbeat_interval: self.heartbeat_interval,
+            reconnect_after: self.reconnect_after,
+            shared: shared.clone(),
+        };
+
+        tokio::spawn(connector.run(stream, rx));
+
+        Ok(Client {
+            shared,
+            outgoing: tx,
+            timeout: self.timeout,
+        })
+    }
+
+    fn url(&self) -> String {
+        let mut params = self.params.clone();
+        params.push(("vsn".to_string(), "2.0.0".to_string()));
+
+        format!(
+            "{}/websocket?{}",
+            self.endpoint.trim_end_matches('/'),
+            serde_urlencoded::to_string(&params).unwrap_or_default()
+        )
+    }
+}
+
+/// Phoenix Channels client, for this crate's endpoints as well as Phoenix itself.
+///
+/// The client speaks the V2 serializer over the endpoint's `/websocket`
+/// transport, keeps the connection alive with heartbeats, and when it drops,
+/// reconnects with backoff and rejoins every joined topic with its original
+/// payload. Clones share the same connection, which is closed once every clone
+/// and channel is dropped.
+///
+/// `wss://` endpoints need one of the TLS features on top of `client`: `rustls`,
+/// which trusts the bundled webpki roots, or `native-tls` for the platform's
+/// TLS library and certificate store.
+///
+/// # Example
+///
+/// ```no_run
+/// use axum_ws::client::Client;
+/// use futures::StreamExt;
+/// use serde_json::{json, Value};
+///
+/// # async fn run() -> Result<(), axum_ws::client::ClientError> {
+/// let client = Client::builder("ws://localhost:3000/socket")
+///     .param("token", "secret")
+///     .connect()
+///     .await?;
+///
+/// let mut room = client.join("room:lobby", json!({})).await?;
+/// let reply: Value = room.push("new_msg", json!({"body": "hello"})).await?;
+///
+/// while let Some(event) = room.next().await {
+///     println!("{}: {}", event.event, event.payload);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Client {
+    shared: Arc<Shared>,
+    outgoing: mpsc::UnboundedSender<String>,
+    timeout: Duration,
+}
+
+impl Client {
+    /// Configure a client for an endpoint mounted at e.g. `ws://localhost:3000/socket`,
+    /// or `wss://example.com/socket` with a TLS feature enabled.
+    pub fn builder(endpoint: impl Into<String>) -> ClientBuilder {
+        ClientBuilder {
+            endpoint: endpoint.into(),
+            params: vec![],
+            heartbeat_interval: Duration::from_secs(30),
+            timeout: Duration::from_secs(10),
+            reconnect_after: Arc::new(|attempt| {
+                let ms = [10, 50, 100, 150, 200, 250, 500, 1000, 2000];
+                let ms = ms.get(attempt as usize - 1).copied().unwrap_or(5000);
+                Duration::from_millis(ms)
+            }),
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.shared.connected.load(Ordering::Acquire)
+    }
+
+    /// Join a topic, failing with [`ClientError::Rejected`] if the server refuses it.
+    pub async fn join(
+        &self,
+        topic: impl Into<Topic>,
+        payload: impl Serialize,
+    ) -> Result<Channel, ClientError> {
+        let topic = topic.into();
+        let payload = serde_json::to_value(payload)?;
+        let join_ref = self.shared.make_ref();
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        {
+            let mut channels = self.shared.channels.lock().unwrap();
+
+            if channels.contains_key(&topic) {
+                return Err(ClientError::AlreadyJoined(topic.to_string()));
+            }
+
+            channels.insert(
+                topic.clone(),
+                Joined {
+                    join_ref: join_ref.clone(),
+                    payload: payload.clone(),
+                    reply: Value::Null,
+                    events: tx,
+                },
+            );
+        }
+
+        let res = self
+            .request(&topic, Event::Join, payload, Some(join_ref.clone()))
+            .await;
+
+        let mut channels = self.shared.channels.lock().unwrap();
+
+        match res {
+            Ok(reply) => {
+                if let Some(joined) = channels.get_mut(&topic) {
+                    joined.reply = reply;
+                }
+
+                drop(channels);
+                Ok(Channel::new(self.clone(), topic, rx))
+            }
+            Err(err) => {
+                if channels.get(&topic).is_some_and(|j| j.join_ref == join_ref) {
+                    channels.remove(&topic);
+                }
+
+                Err(err)
+            }
+        }
+    }
+
+    async fn request(
+        &self,
+        topic: &Topic,
+        event: Event,
+        payload: Value,
+        join_ref: Option<String>,
+    ) -> Result<Value, ClientError> {
+        if !self.is_connected() {
+            return Err(ClientError::Disconnected);
+        }
+
+        let message_ref = self.shared.make_ref();
+        let (tx, rx) = oneshot::channel();
+
+        self.shared
+            .pending
+            .lock()
+            .unwrap()
+            .insert(message_ref.clone(), Pending::Reply(tx));
+
+        let message = Message {
+            join_ref,
+            message_ref: Some(message_ref.clone()),
+            topic: topic.clone(),
+            event,
+            payload: payload.into(),
+        };
+
+        if self.outgoing.send(message.to_string()).is_err() {
+            self.shared.pending.lock().unwrap().remove(&message_ref);
+            return Err(ClientError::Disconnected);
+        }
+
+        let reply = match tokio::time::timeout(self.timeout, rx).await {
+            Ok(Ok(reply)) => reply,
+            Ok(Err(_)) => return Err(ClientError::Disconnected),
+            Err(_) => {
+                self.shared.pending.lock().unwrap().remove(&message_ref);
+                return Err(ClientError::Timeout);
+            }
+        };
+
+        match reply.get("status").and_then(Value::as_str) {
+            Some("ok") => Ok(reply["response"].clone()),
+            Some(_) => Err(ClientError::Rejected(reply["response"].clone())),
+            None => Ok(reply),
+        }
+    }
+
+    /// Send a message without waiting for its reply.
+    fn cast(&self, topic: &Topic, event: Event, payload: Value, join_ref: Option<String>) {
+        if !self.is_connected() {
+            return;
+        }
+
+        let message = Message {
+            join_ref,
+            message_ref: Some(self.shared.make_ref()),
+            topic: topic.clone(),
+            event,
+            payload: payload.into(),
+        };
+
+        let _ = self.outgoing.send(message.to_string());
+    }
+}
+
+enum Pending {
+    Reply(oneshot::Sender<Value>),
+    Rejoin(Topic, String),
+}
+
+struct Joined {
+    join_ref: String,
+    payload: Value,
+    reply: Value,
+    events: mpsc::UnboundedSender<ChannelEvent>,
+}
+
+/// State shared by the client handles and the task driving the connection.
+#[derive(Default)]
+struct Shared {
+    refs: AtomicU64,
+    connected: AtomicBool,
+    pending: Mutex<HashMap<String, Pending>>,
+    channels: Mutex<HashMap<Topic, Joined>>,
+}
+
+impl Shared {
+    fn make_ref(&self) -> String {
+        (self.refs.fetch_add(1, Ordering::Relaxed) + 1).to_string()
+    }
+
+    fn join_ref(&self, topic: &Topic) -> Option<String> {
+        let channels = self.channels.lock().unwrap();
+        channels.get(topic).map(|joined| joined.join_ref.clone())
+    }
+
+    fn join_reply(&self, topic: &Topic) -> Option<Value> {
+        let channels = self.channels.lock().unwrap();
+        channels.get(topic).map(|joined| joined.reply.clone())
+    }
+
+    fn remove_channel(&self, topic: &Topic) -> Option<String> {
+        let mut channels = self.channels.lock().unwrap();
+        channels.remove(topic).map(|joined| joined.join_ref)
+    }
+
+    /// Fail every request in flight, they cannot be answered on a new connection.
+    fn disconnect(&self) {
+        self.connected.store(false, Ordering::Release);
+        self.pending.lock().unwrap().clear();
+    }
+
+    /// Join messages for every topic joined before the connection dropped.
+    fn rejoin(&self) -> Vec<String> {
+        let mut channels = self.channels.lock().unwrap();
+        let mut pending = self.pending.lock().unwrap();
+
+        channels
+            .iter_mut()
+            .map(|(topic, joined)| {
+                let message_ref = self.make_ref();
+                joined.join_ref = self.make_ref();
+
+                pending.insert(
+                    message_ref.clone(),
+                    Pending::Rejoin(topic.clone(), joined.join_ref.clone()),
+                );
+
+                Message {
+                    join_ref: Some(joined.join_ref.clone()),
+                    message_ref: Some(message_ref),
+                    topic: topic.clone(),
+                    event: Event::Join,
+                    payload: joined.payload.clone().into(),
+                }
+                .to_string()
+            })
+            .collect()
+    }
+
+    fn dispatch(&self, text: &str, heartbeat: &mut Option<String>) {
+        let Ok(message) = Message::try_from(text) else {
+            return;
+        };
+
+        // Only replies answer requests: this crate's broadcasts carry the ref of
+        // the message that triggered them, and its heartbeat replies are named `heartbeat`.
+        if let Some(message_ref) = message.message_ref.as_ref() {
+            if &*message.topic == "phoenix" && heartbeat.as_ref() == Some(message_ref) {
+                *heartbeat = None;
+                return;
+            }
+        }
+
+        if let (Event::Reply, Some(message_ref)) = (&message.event, message.message_ref.as_ref()) {
+            let pending = self.pending.lock().unwrap().remove(message_ref);
+
+            match pending {
+                Some(Pending::Reply(tx)) => {
+                    let _ = tx.send(message.payload.into());
+                    return;
+                }
+                Some(Pending::Rejoin(topic, join_ref)) => {
+                    let mut reply: Value = message.payload.into();
+                    let mut channels = self.channels.lock().unwrap();
+
+                    match channels.get_mut(&topic) {
+                        Some(joined) if joined.join_ref == join_ref => {
+                            if reply["status"] == "ok" {
+                                joined.reply = reply["response"].take();
+                            } else {
+                                channels.remove(&topic);
+                            }
+                        }
+                        _ => {}
+                    }
+
+                    return;
+                }
+                None => {}
+            }
+        }
+
+        let mut channels = self.channels.lock().unwrap();
+        let Some(joined) = channels.get(&message.topic) else {
+            return;
+        };
+
+        match message.event {
+            Event::Reply => {}
+            Event::Close => {
+                if message.join_ref.is_none() || message.join_ref == Some(joined.join_ref.clone()) {
+                    channels.remove(&message.topic);
+                }
+            }
+            event => {
+                let _ = joined.events.send(ChannelEvent {
+                    event: event.to_string(),
+                    payload: message.payload.into(),
+                });
+            }
+        }
+    }
+}
+
+/// The task owning the socket: it writes what the handles send, dispatches what
+/// the server sends, and reconnects until every handle is gone.
+struct Connector {
+    url: String,
+    heartbeat_interval: Duration,
+    reconnect_after: ReconnectAfter,
+    shared: Arc<Shared>,
+}
+
+impl Connector {
+    async fn run(self, mut stream: Stream, mut outgoing: mpsc::UnboundedReceiver<String>) {
+        loop {
+            if !self.serve(&mut stream, &mut outgoing).await {
+                return;
+            }
+
+            self.shared.disconnect();
+
+            let mut attempt = 0;
+            stream = loop {
+                attempt += 1;
+
+                let sleep = tokio::time::sleep((self.reconnect_after)(attempt));
+                tokio::pin!(sleep);
+
+                // Whatever is sent meanwhile was already failed by `disconnect`.
+                loop {
+                    tokio::select! {
+                        _ = &mut sleep => break,
+                        text = outgoing.recv() => if text.is_none() { return },
+                    }
+                }
+
+                if let Ok((stream, _)) = tokio_tungstenite::connect_async(self.url.as_str()).await {
+                    break stream;
+                }
+            };
+
+            while outgoing.try_recv().is_ok() {}
+
+            for text in self.shared.rejoin() {
+                let _ = stream.send(tungstenite::Message::Text(text)).await;
+            }
+
+            self.shared.connected.store(true, Ordering::Release);
+        }
+    }
+
+    /// Drive one connection, returning whether to reconnect once it is lost.
+    async fn serve(
+        &self,
+        stream: &mut Stream,
+        outgoing: &mut mpsc::UnboundedReceiver<String>,
+    ) -> bool {
+        let mut heartbeat = tokio::time::interval_at(
+            Instant::now() + self.heartbeat_interval,
+            self.heartbeat_interval,
+        );
+        let mut pending_heartbeat = None;
+
+        loop {
+            tokio::select! {
+                frame = stream.next() => match frame {
+                    Some(Ok(tungstenite::Message::Text(text))) => {
+                        self.shared.dispatch(&text, &mut pending_heartbeat);
+                    }
+                    Some(Ok(tungstenite::Message::Close(_))) | Some(Err(_)) | None => return true,
+                    Some(Ok(_)) => {}
+                },
+                text = outgoing.recv() => {
+                    let Some(text) = text else {
+                        let _ = stream.close(None).await;
+                        return false;
+                    };
+
+                    if stream.send(tungstenite::Message::Text(text)).await.is_err() {
+                        return true;
+                    }
+                }
+                _ = heartbeat.tick() => {
+                    if pending_heartbeat.is_some() {
+                        return true;
+                    }
+
+                    let message_ref = self.shared.make_ref();
+                    let message = Message {
+                        join_ref: None,
+                        message_ref: Some(message_ref.clone()),
+                        topic: "phoenix".into(),
+                        event: Event::Heartbeat,
+                        payload: json!({}).into(),
+                    };
+
+                    pending_heartbeat = Some(message_ref);
+
+                    if stream.send(tungstenite::Message::Text(message.to_string())).await.is_err() {
+                        return true;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{handler::Response, Payload, Socket, WebSocket};
+    use axum::Router;
+    use serde_json::json;
+
+    async fn serve() -> String {
+        async fn join(topic: Topic, _payload: Payload, _socket: Socket) -> anyhow::Result<Value> {
+            if &*topic == "room:private" {
+                anyhow::bail!("unauthorized");
+            }
+
+            Ok(json!({"joined": &*topic}))
+        }
+
+        async fn shout(payload: Payload, socket: Socket) -> Response {
+            let socket = socket.lock().await;
+            let _ = socket.broadcast("shout", Ok(payload.into())).await;
+
+            Response::Ok(json!({"shouted": true}))
+        }
+
+        let websocket = WebSocket::<()>::new("/client").channel(
+            "room:*",
+            crate::Channel::new().join(join).handler("shout", shout),
+        );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            axum::serve(listener, Router::from(websocket))
+                .await
+                .unwrap();
+        });
+
+        format!("ws://{addr}/client")
+    }
+
+    #[tokio::test]
+    async fn client_should_join_push_and_receive_broadcasts() {
+        let client = Client::builder(serve().await).connect().await.unwrap();
+
+        let mut room = client.join("room:lobby", json!({})).await.unwrap();
+        assert_eq!(
+            room.join_reply::<Value>().unwrap(),
+            json!({"joined": "room:lobby"})
+        );
+
+        let reply: Value = room.push("shout", json!({"body": "hi"})).await.unwrap();
+        assert_eq!(reply, json!({"shouted": true}));
+
+        let event = room.next().await.unwrap();
+        assert_eq!(event.event, "shout");
+
+        assert!(matches!(
+            client.join("room:lobby", json!({})).await,
+            Err(ClientError::AlreadyJoined(_))
+        ));
+        assert!(matches!(
+            client.join("room:private", json!({})).await,
+            Err(ClientError::Rejected(_))
+        ));
+
+        room.leave().await.unwrap();
+        assert!(client.join("room:lobby", json!({})).await.is_ok());
+    }
+}
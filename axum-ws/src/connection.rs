@@ -0,0 +1,516 @@
+use crate::{
+    channel::Channel,
+    event::Event,
+    handler::{IntoResponse, Response},
+    message::Message,
+    outbound::{self, OutboundReceiver},
+    serializer::Serializer,
+    socket,
+    topic::Topic,
+    topic_router::Match,
+    user_id::UserId,
+    websocket::WebSocket,
+    websocket_error::WebSocketError,
+    websocket_state::WEBSOCKET_STATE,
+    Socket,
+};
+use axum::{http::StatusCode, response::IntoResponse as _};
+use serde_json::{json, Value};
+use std::{collections::HashMap, sync::Arc};
+use tokio::{sync::Mutex, time::Instant};
+use tracing::{Instrument, Span};
+
+#[cfg(feature = "metrics")]
+use crate::metrics::{Tracked, METRICS, OTHER_EVENT};
+
+/// Channel state of one client, independent of the transport carrying its frames.
+pub(crate) struct Connection<T> {
+    websocket: Arc<WebSocket<T>>,
+    socket: Socket,
+    sockets: HashMap<Topic, Socket>,
+    spans: HashMap<Topic, Span>,
+    serializer: Serializer,
+    span: Span,
+    #[cfg(feature = "metrics")]
+    tracked: HashMap<Topic, Tracked>,
+    #[cfg(feature = "metrics")]
+    _connection: Tracked,
+}
+
+/// The `terminate` callbacks of the channels a connection just left.
+pub(crate) struct Terminating(pub(crate) Vec<(Arc<Channel>, Topic, Socket, Span)>);
+
+impl Terminating {
+    pub(crate) async fn run(self) {
+        for (channel, topic, socket, span) in self.0 {
+            if let Some(terminate) = channel.terminate.as_ref() {
+                terminate.call(topic, socket).instrument(span).await;
+            }
+        }
+    }
+}
+
+impl<T> Connection<T> {
+    /// Leave every joined channel without waiting, for the caller to run their
+    /// `terminate` callbacks, e.g. from `Drop`.
+    pub(crate) fn leave_all(&mut self, user_id: &UserId) -> Terminating {
+        #[cfg(feature = "metrics")]
+        self.tracked.clear();
+
+        let channels = std::mem::take(&mut self.sockets)
+            .into_iter()
+            .filter_map(|(topic, socket)| {
+                let span = self
+                    .spans
+                    .remove(&topic)
+                    .unwrap_or_else(|| self.span.clone());
+                let channel = WEBSOCKET_STATE
+                    .take_subscription(user_id, &topic)?
+                    .channel?;
+
+                Some((channel, topic, socket, span))
+            })
+            .collect();
+
+        Terminating(channels)
+    }
+}
+
+impl<T> Connection<T>
+where
+    T: Default + Send + Sync + 'static,
+{
+    /// Run the endpoint's `connect` and `id` callbacks for a new client.
+    ///
+    /// A client or server error returned by `connect` rejects the client and is
+    /// handed back as is, and while the endpoint shuts down clients are refused
+    /// with `503 Service Unavailable`.
+    pub(crate) async fn authenticate(
+        websocket: &WebSocket<T>,
+        params: Value,
+    ) -> Result<Socket, axum::response::Response> {
+        let draining = WEBSOCKET_STATE
+            .get_shutdown(&websocket.path)
+            .is_some_and(|shutdown| shutdown.is_draining());
+
+        if draining {
+            tracing::info!(path = %websocket.path, result = "error", status = 503, "socket connected");
+            return Err((StatusCode::SERVICE_UNAVAILABLE, "shutting down").into_response());
+        }
+
+        let started = Instant::now();
+        let user_id = nanoid::nanoid!();
+        let socket = Arc::new(Mutex::new(socket::Socket::new(
+            user_id,
+            websocket.path.clone(),
+        )));
+
+        if let Some(connect) = websocket.connect.as_ref() {
+            let res = connect.call(params, socket.clone()).await;
+
+            if res.status().is_client_error() || res.status().is_server_error() {
+                tracing::info!(
+                    path = %websocket.path,
+                    duration = ?started.elapsed(),
+                    result = "error",
+                    status = res.status().as_u16(),
+                    "socket connected"
+                );
+                return Err(res);
+            }
+        }
+
+        if let Some(id) = websocket.id.as_ref() {
+            if let Some(gen_id) = id.call(socket.clone()).await {
+                socket.lock().await.set_id(gen_id);
+            }
+        }
+
+        let id = socket.lock().await.id.clone();
+
+        tracing::info!(
+            path = %websocket.path,
+            id = %id,
+            duration = ?started.elapsed(),
+            result = "ok",
+            "socket connected"
+        );
+
+        Ok(socket)
+    }
+
+    /// Register the outbound queue of an authenticated socket and start tracking its channels.
+    pub(crate) async fn open(
+        websocket: Arc<WebSocket<T>>,
+        socket: Socket,
+        serializer: Serializer,
+    ) -> (Self, OutboundReceiver) {
+        let (tx, rx) = outbound::channel(
+            serializer,
+            websocket.buffer_size,
+            websocket.overflow_policy,
+            websocket.block_timeout,
+        );
+        let (socket_id, user_id) = {
+            let socket = socket.lock().await;
+            (socket.id.clone(), socket.connection_id.clone())
+        };
+
+        let span = tracing::info_span!(
+            "socket",
+            path = %websocket.path,
+            id = %socket_id,
+            connection_id = %user_id,
+            serializer = ?serializer
+        );
+
+        WEBSOCKET_STATE.insert_connection(
+            user_id.clone().into(),
+            socket_id,
+            &websocket.path,
+            serializer,
+        );
+        WEBSOCKET_STATE.insert_sender(user_id, tx);
+
+        let connection = Self {
+            #[cfg(feature = "metrics")]
+            tracked: HashMap::new(),
+            #[cfg(feature = "metrics")]
+            _connection: METRICS.connection(&websocket.path),
+            websocket,
+            socket,
+            sockets: HashMap::new(),
+            spans: HashMap::new(),
+            serializer,
+            span,
+        };
+
+        (connection, rx)
+    }
+
+    /// Span of the connection, covering its channels' spans.
+    pub(crate) fn span(&self) -> &Span {
+        &self.span
+    }
+
+    /// Decode and dispatch one inbound text frame.
+    pub(crate) async fn handle_text(&mut self, text: &str) -> Result<(), WebSocketError> {
+        let message = self.serializer.decode(text)?;
+
+        #[cfg(feature = "metrics")]
+        METRICS.message_in(&self.event_label(&message));
+
+        match message.event {
+            Event::Join => {
+                let topic = message.topic.clone();
+                let channel = self.websocket.get_channel(&topic);
+
+                let banned = {
+                    let mut socket = self.socket.lock().await;
+                    socket.set_message(message.clone());
+                    socket.set_params(
+                        channel
+                            .as_ref()
+                            .map(|m| m.params.clone())
+                            .unwrap_or_default(),
+                    );
+
+                    WEBSOCKET_STATE
+                        .is_banned(&(self.websocket.path.clone(), topic.clone()), &socket.id)
+                };
+
+                if banned {
+                    tracing::info!(
+                        parent: &self.span,
+                        topic = %&*topic,
+                        result = "error",
+                        reason = "banned",
+                        "channel joined"
+                    );
+
+                    let message = Message::builder()
+                        .event("reply")
+                        .payload(Response::Err("banned".into()))
+                        .build()
+                        .unwrap();
+
+                    let socket = self.socket.lock().await;
+                    socket.push_message(message).await?;
+                } else if let Some(Match {
+                    value: channel,
+                    pattern,
+                    ..
+                }) = channel
+                {
+                    if let Some(join) = channel.join.as_ref() {
+                        let span = tracing::info_span!(parent: &self.span, "channel", topic = %&*topic, pattern);
+                        let started = Instant::now();
+                        let res = join
+                            .call(topic.clone(), message.payload.clone(), self.socket.clone())
+                            .instrument(span.clone())
+                            .await;
+
+                        let duration = started.elapsed();
+
+                        tracing::info!(
+                            parent: &span,
+                            duration = ?duration,
+                            result = if res.is_ok() { "ok" } else { "error" },
+                            "channel joined"
+                        );
+
+                        #[cfg(feature = "metrics")]
+                        METRICS.handler_duration(&self.websocket.path, "phx_join", duration);
+
+                        let socket = self.socket.lock().await;
+
+                        if res.is_ok() {
+                            let mut socket = socket.clone();
+
+                            socket.set_joined(true);
+                            socket.set_topic(topic.clone());
+
+                            let user_id: UserId = socket.connection_id.clone().into();
+                            let channel_socket = Arc::new(Mutex::new(socket.clone()));
+
+                            WEBSOCKET_STATE.insert_subscription(
+                                user_id.clone(),
+                                topic.clone(),
+                                channel_socket.clone(),
+                                socket,
+                                Some(channel.clone()),
+                            );
+                            WEBSOCKET_STATE
+                                .insert_user((self.websocket.path.clone(), topic.clone()), user_id);
+
+                            self.sockets.insert(topic.clone(), channel_socket);
+                            self.spans.insert(topic.clone(), span);
+
+                            #[cfg(feature = "metrics")]
+                            self.tracked.insert(
+                                topic.clone(),
+                                METRICS.channel(&self.websocket.path, pattern),
+                            );
+                        } else {
+                            let user_id = socket.connection_id.clone().into();
+                            WEBSOCKET_STATE.remove_relays(&user_id, &topic);
+                        }
+
+                        let payload: Value = res.into_response().into();
+                        let message = Message::builder()
+                            .event("reply")
+                            .payload(payload)
+                            .build()
+                            .unwrap();
+
+                        socket.push_message(message).await?;
+                    }
+                } else {
+                    tracing::info!(
+                        parent: &self.span,
+                        topic = %&*topic,
+                        result = "error",
+                        reason = "unmatched topic",
+                        "channel joined"
+                    );
+
+                    let message = Message::builder()
+                        .event("reply")
+                        .payload(Response::Err("unmatched topic".into()))
+                        .build()
+                        .unwrap();
+
+                    let socket = self.socket.lock().await;
+                    socket.push_message(message).await?;
+                }
+            }
+            Event::Leave => {
+                let socket = self.socket.clone();
+                let mut socket = socket.lock().await;
+                let topic = message.topic.clone();
+                socket.set_message(message.clone());
+
+                let message = Message::builder()
+                    .event("reply")
+                    .payload(Response::Ok(json!({})))
+                    .build()
+                    .unwrap();
+
+                socket.push_message(message).await?;
+
+                let user_id = socket.connection_id.clone().into();
+                let joined = WEBSOCKET_STATE
+                    .take_subscription(&user_id, &topic)
+                    .is_some();
+
+                WEBSOCKET_STATE
+                    .remove_user(&(self.websocket.path.clone(), topic.clone()), &user_id);
+
+                if let Some((channel_socket, span)) = self.forget(&topic) {
+                    tracing::info!(parent: &span, "channel left");
+
+                    // A kicked channel has already been terminated.
+                    if joined {
+                        self.terminate_channel(topic, channel_socket, span).await;
+                    }
+                }
+
+                let message = Message::builder()
+                    .event("close")
+                    .payload(Response::NoReply)
+                    .build()
+                    .unwrap();
+
+                socket.push_message(message).await?;
+            }
+            Event::Heartbeat => {
+                tracing::debug!(parent: &self.span, "heartbeat");
+
+                let mut socket = self.socket.lock().await;
+                socket.set_message(message.clone());
+
+                let message = Message::builder()
+                    .event("heartbeat")
+                    .payload(Response::Ok(json!({})))
+                    .build()
+                    .unwrap();
+
+                socket.push_message(message).await?;
+            }
+            Event::Custom(ref event) => {
+                let user_id = self.socket.lock().await.connection_id.clone().into();
+
+                if !WEBSOCKET_STATE.has_subscription(&user_id, &message.topic) {
+                    self.forget(&message.topic);
+                }
+
+                if let Some(socket) = self.sockets.get(&message.topic) {
+                    {
+                        let mut socket = socket.lock().await;
+                        socket.set_message(message.clone());
+                    }
+
+                    if let Some(Match { value: channel, .. }) =
+                        self.websocket.get_channel(&message.topic)
+                    {
+                        let payload = message.payload.clone();
+                        let handled = match channel.handler.get(event) {
+                            Some(handler) => Some(handler.call(payload, socket.clone())),
+                            None => channel.handle_in.as_ref().map(|handle_in| {
+                                handle_in.call(event.clone(), payload, socket.clone())
+                            }),
+                        };
+
+                        if let Some(handled) = handled {
+                            let span = self.channel_span(&message.topic).clone();
+                            let started = Instant::now();
+                            let res = handled.instrument(span.clone()).await;
+
+                            let duration = started.elapsed();
+
+                            tracing::debug!(
+                                parent: &span,
+                                event = %event,
+                                duration = ?duration,
+                                status = match res {
+                                    Response::Ok(_) => "ok",
+                                    Response::Err(_) => "error",
+                                    Response::NoReply => "noreply",
+                                },
+                                "channel handled in"
+                            );
+
+                            #[cfg(feature = "metrics")]
+                            METRICS.handler_duration(
+                                &self.websocket.path,
+                                match channel.handler.contains_key(event) {
+                                    true => event,
+                                    false => OTHER_EVENT,
+                                },
+                                duration,
+                            );
+
+                            if res != Response::NoReply {
+                                let payload: Value = res.into_response().into();
+                                let message = Message::builder()
+                                    .event("reply")
+                                    .payload(payload)
+                                    .build()
+                                    .unwrap();
+
+                                let socket = socket.lock().await;
+                                socket.push_message(message).await?;
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Run the `terminate` callback of every joined channel, leaving them all.
+    pub(crate) async fn terminate(&mut self) {
+        let user_id = self.socket.lock().await.connection_id.clone().into();
+        self.leave_all(&user_id).run().await;
+    }
+
+    /// Stop tracking a channel, returning its socket and span if it was joined.
+    fn forget(&mut self, topic: &Topic) -> Option<(Socket, Span)> {
+        #[cfg(feature = "metrics")]
+        self.tracked.remove(topic);
+
+        let socket = self.sockets.remove(topic)?;
+        let span = self
+            .spans
+            .remove(topic)
+            .unwrap_or_else(|| self.span.clone());
+
+        Some((socket, span))
+    }
+
+    async fn terminate_channel(&self, topic: Topic, socket: Socket, span: Span) {
+        if let Some(Match { value: channel, .. }) = self.websocket.get_channel(&topic) {
+            if let Some(terminate) = channel.terminate.as_ref() {
+                terminate.call(topic, socket).instrument(span).await;
+            }
+        }
+    }
+
+    /// The `event` label of an inbound message, only events with a handler of
+    /// their own being labelled by name besides the protocol's.
+    #[cfg(feature = "metrics")]
+    fn event_label(&self, message: &Message) -> String {
+        let Event::Custom(event) = &message.event else {
+            return message.event.to_string();
+        };
+
+        match self.websocket.get_channel(&message.topic) {
+            Some(Match { value: channel, .. }) if channel.handler.contains_key(event) => {
+                event.clone()
+            }
+            _ => OTHER_EVENT.to_string(),
+        }
+    }
+
+    /// Span of a joined channel, or of the connection for a topic that is not joined.
+    fn channel_span(&self, topic: &Topic) -> &Span {
+        self.spans.get(topic).unwrap_or(&self.span)
+    }
+
+    /// The socket of a joined topic.
+    #[cfg(any(test, feature = "test-util"))]
+    pub(crate) fn channel_socket(&self, topic: &Topic) -> Option<Socket> {
+        self.sockets.get(topic).cloned()
+    }
+
+    /// Drop every registration the connection holds in the shared state.
+    pub(crate) async fn close(&self, reason: &str) {
+        let user_id = self.socket.lock().await.connection_id.clone().into();
+        WEBSOCKET_STATE.clearn_user(&user_id);
+
+        tracing::info!(parent: &self.span, reason, "socket disconnected");
+    }
+}
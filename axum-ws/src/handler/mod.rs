@@ -1,5 +1,6 @@
 use crate::{payload::Payload, topic::Topic, Socket};
 use anyhow::Result;
+use axum::http::HeaderMap;
 use futures::future::BoxFuture;
 use serde_json::Value;
 
@@ -34,6 +35,32 @@ where
     }
 }
 
+pub(crate) trait Authorize: Send + Sync {
+    fn call(&self, headers: HeaderMap) -> BoxFuture<'static, axum::response::Response>;
+}
+
+pub(crate) struct AuthorizeWrapper<F> {
+    handler: F,
+}
+
+impl<F> Authorize for AuthorizeWrapper<F>
+where
+    F: Fn(HeaderMap) -> BoxFuture<'static, axum::response::Response> + Send + Sync + 'static,
+{
+    fn call(&self, headers: HeaderMap) -> BoxFuture<'static, axum::response::Response> {
+        (self.handler)(headers)
+    }
+}
+
+impl<F> AuthorizeWrapper<F>
+where
+    F: Fn(HeaderMap) -> BoxFuture<'static, axum::response::Response> + Send + Sync + 'static,
+{
+    pub fn new(handler: F) -> Self {
+        AuthorizeWrapper { handler }
+    }
+}
+
 pub(crate) trait Id: Send + Sync {
     fn call(&self, socket: Socket) -> BoxFuture<'static, Option<String>>;
 }
@@ -96,6 +123,134 @@ where
     }
 }
 
+pub(crate) trait Terminate: Send + Sync {
+    fn call(&self, topic: Topic, socket: Socket) -> BoxFuture<'static, ()>;
+}
+
+pub(crate) struct TerminateWrapper<F> {
+    handler: F,
+}
+
+impl<F> Terminate for TerminateWrapper<F>
+where
+    F: Fn(Topic, Socket) -> BoxFuture<'static, ()> + Send + Sync + 'static,
+{
+    fn call(&self, topic: Topic, socket: Socket) -> BoxFuture<'static, ()> {
+        (self.handler)(topic, socket)
+    }
+}
+
+impl<F> TerminateWrapper<F>
+where
+    F: Fn(Topic, Socket) -> BoxFuture<'static, ()> + Send + Sync + 'static,
+{
+    pub fn new(handler: F) -> Self {
+        TerminateWrapper { handler }
+    }
+}
+
+pub(crate) trait Intercept: Send + Sync {
+    fn call(
+        &self,
+        topic: Topic,
+        event: String,
+        payload: Payload,
+        socket: Socket,
+    ) -> BoxFuture<'static, Option<Value>>;
+}
+
+pub(crate) struct InterceptWrapper<F> {
+    handler: F,
+}
+
+impl<F> Intercept for InterceptWrapper<F>
+where
+    F: Fn(Topic, String, Payload, Socket) -> BoxFuture<'static, Option<Value>>
+        + Send
+        + Sync
+        + 'static,
+{
+    fn call(
+        &self,
+        topic: Topic,
+        event: String,
+        payload: Payload,
+        socket: Socket,
+    ) -> BoxFuture<'static, Option<Value>> {
+        (self.handler)(topic, event, payload, socket)
+    }
+}
+
+impl<F> InterceptWrapper<F>
+where
+    F: Fn(Topic, String, Payload, Socket) -> BoxFuture<'static, Option<Value>>
+        + Send
+        + Sync
+        + 'static,
+{
+    pub fn new(handler: F) -> Self {
+        InterceptWrapper { handler }
+    }
+}
+
+pub(crate) trait HandleIn: Send + Sync {
+    fn call(&self, event: String, payload: Payload, socket: Socket)
+        -> BoxFuture<'static, Response>;
+}
+
+pub(crate) struct HandleInWrapper<F> {
+    handler: F,
+}
+
+impl<F> HandleIn for HandleInWrapper<F>
+where
+    F: Fn(String, Payload, Socket) -> BoxFuture<'static, Response> + Send + Sync + 'static,
+{
+    fn call(
+        &self,
+        event: String,
+        payload: Payload,
+        socket: Socket,
+    ) -> BoxFuture<'static, Response> {
+        (self.handler)(event, payload, socket)
+    }
+}
+
+impl<F> HandleInWrapper<F>
+where
+    F: Fn(String, Payload, Socket) -> BoxFuture<'static, Response> + Send + Sync + 'static,
+{
+    pub fn new(handler: F) -> Self {
+        HandleInWrapper { handler }
+    }
+}
+
+pub(crate) trait Info: Send + Sync {
+    fn call(&self, message: Value, socket: Socket) -> BoxFuture<'static, ()>;
+}
+
+pub(crate) struct InfoWrapper<F> {
+    handler: F,
+}
+
+impl<F> Info for InfoWrapper<F>
+where
+    F: Fn(Value, Socket) -> BoxFuture<'static, ()> + Send + Sync + 'static,
+{
+    fn call(&self, message: Value, socket: Socket) -> BoxFuture<'static, ()> {
+        (self.handler)(message, socket)
+    }
+}
+
+impl<F> InfoWrapper<F>
+where
+    F: Fn(Value, Socket) -> BoxFuture<'static, ()> + Send + Sync + 'static,
+{
+    pub fn new(handler: F) -> Self {
+        InfoWrapper { handler }
+    }
+}
+
 pub(crate) trait Handler: Send + Sync {
     fn call(&self, payload: Payload, socket: Socket) -> BoxFuture<'static, Response>;
 }
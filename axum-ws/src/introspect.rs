@@ -0,0 +1,329 @@
+use crate::{
+    handler::{Authorize, AuthorizeWrapper},
+    topic::Topic,
+    user_id::UserId,
+    websocket::WebSocket,
+    websocket_state::WEBSOCKET_STATE,
+};
+use axum::{
+    extract::Path,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use futures::Future;
+use serde::{Serialize, Serializer};
+use serde_json::json;
+use std::{
+    collections::HashSet,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A topic of an endpoint and how many subscribers it has on this node.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TopicInfo {
+    pub topic: Topic,
+    pub subscribers: usize,
+}
+
+/// Snapshot of a connection, see [`WebSocket::connection`].
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionInfo {
+    /// The socket id, as set by the endpoint's `id` callback.
+    pub id: String,
+    pub connection_id: String,
+    /// Protocol version spoken by the client, `None` for SSE streams.
+    pub vsn: Option<&'static str>,
+    /// Serialized as milliseconds since the Unix epoch.
+    #[serde(serialize_with = "unix_millis")]
+    pub connected_at: SystemTime,
+    pub topics: Vec<Topic>,
+    /// Messages waiting in the connection's outbound queue.
+    pub queued: usize,
+}
+
+impl<T> WebSocket<T>
+where
+    T: Default + Send + Sync + 'static,
+{
+    /// Topics of the endpoint with subscribers on this node, sorted by name.
+    pub fn topics() -> Vec<TopicInfo> {
+        let Some(path) = WEBSOCKET_STATE.get_path::<T>() else {
+            return vec![];
+        };
+
+        let mut topics = WEBSOCKET_STATE
+            .get_topics(&path)
+            .into_iter()
+            .map(|topic| TopicInfo {
+                subscribers: WEBSOCKET_STATE
+                    .get_users(&(path.clone(), topic.clone()))
+                    .len(),
+                topic,
+            })
+            .filter(|info| info.subscribers > 0)
+            .collect::<Vec<_>>();
+
+        topics.sort_by(|a, b| a.topic.cmp(&b.topic));
+        topics
+    }
+
+    /// Socket ids subscribed to a topic on this node, sorted.
+    pub fn subscribers(topic: &str) -> Vec<String> {
+        let Some(path) = WEBSOCKET_STATE.get_path::<T>() else {
+            return vec![];
+        };
+
+        let mut ids = WEBSOCKET_STATE.get_users(&(path, topic.into()));
+        ids.sort();
+        ids
+    }
+
+    /// Topics the connections with socket id `id` have joined on this endpoint, sorted.
+    pub fn topics_of(id: &str) -> Vec<Topic> {
+        let Some(path) = WEBSOCKET_STATE.get_path::<T>() else {
+            return vec![];
+        };
+
+        let mut topics = WEBSOCKET_STATE
+            .get_connections_of(id)
+            .iter()
+            .flat_map(|key| WEBSOCKET_STATE.get_user_topics(&path, key))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        topics.sort();
+        topics
+    }
+
+    /// Number of connections to the endpoint on this node, over any transport.
+    pub fn connection_count() -> usize {
+        WEBSOCKET_STATE
+            .get_path::<T>()
+            .map_or(0, |path| WEBSOCKET_STATE.count_connections(&path))
+    }
+
+    /// Snapshot of the connection with connection id `connection_id`.
+    pub fn connection(connection_id: &str) -> Option<ConnectionInfo> {
+        let path = WEBSOCKET_STATE.get_path::<T>()?;
+        let key = UserId::from(connection_id);
+        let meta = WEBSOCKET_STATE.get_connection(connection_id)?;
+
+        if meta.path != path {
+            return None;
+        }
+
+        let mut topics = WEBSOCKET_STATE
+            .get_user_topics(&path, &key)
+            .into_iter()
+            .collect::<Vec<_>>();
+        topics.sort();
+
+        Some(ConnectionInfo {
+            id: meta.socket_id,
+            connection_id: connection_id.to_string(),
+            vsn: meta.serializer.vsn(),
+            connected_at: meta.connected_at,
+            topics,
+            queued: WEBSOCKET_STATE.get_sender(&key).map_or(0, |tx| tx.len()),
+        })
+    }
+
+    /// Snapshots of every connection with socket id `id`, oldest first.
+    pub fn connections_of(id: &str) -> Vec<ConnectionInfo> {
+        let mut connections = WEBSOCKET_STATE
+            .get_connections_of(id)
+            .iter()
+            .filter_map(|key| Self::connection(key.as_str()))
+            .collect::<Vec<_>>();
+
+        connections.sort_by_key(|connection| connection.connected_at);
+        connections
+    }
+
+    /// Expose the introspection API as JSON for dashboards:
+    ///
+    /// - `GET {path}/admin`: `{"connections": n, "topics": [{"topic", "subscribers"}]}`
+    /// - `GET {path}/admin/topics/{topic}`: `{"topic", "subscribers": [id]}`
+    /// - `GET {path}/admin/sockets/{id}`: the [`ConnectionInfo`]s of a socket id
+    /// - `GET {path}/admin/connections/{connection_id}`: the [`ConnectionInfo`] of a connection
+    ///
+    /// Every request is first passed to `authorize`, and one answered with a client
+    /// or server error status is rejected with that response, as with `publish`.
+    pub fn admin<F, Fut, Res>(mut self, authorize: F) -> Self
+    where
+        F: Fn(HeaderMap) -> Fut + Clone + Send + Sync + 'static,
+        Fut: Future<Output = Res> + Send + 'static,
+        Res: IntoResponse,
+    {
+        self.admin = Some(Box::new(AuthorizeWrapper::new(move |headers| {
+            let authorize = authorize.clone();
+
+            Box::pin(async move {
+                let res = authorize(headers).await;
+                res.into_response()
+            })
+        })) as Box<dyn Authorize + Send + Sync>);
+        self
+    }
+
+    /// `GET {path}/admin`
+    pub(crate) async fn admin_summary(
+        headers: HeaderMap,
+        Extension(websocket): Extension<Arc<WebSocket<T>>>,
+    ) -> Response {
+        if let Err(res) = websocket.authorize_admin(headers).await {
+            return res;
+        }
+
+        Json(json!({
+            "connections": Self::connection_count(),
+            "topics": Self::topics(),
+        }))
+        .into_response()
+    }
+
+    /// `GET {path}/admin/topics/{topic}`
+    pub(crate) async fn admin_topic(
+        headers: HeaderMap,
+        Path(topic): Path<String>,
+        Extension(websocket): Extension<Arc<WebSocket<T>>>,
+    ) -> Response {
+        if let Err(res) = websocket.authorize_admin(headers).await {
+            return res;
+        }
+
+        Json(json!({
+            "topic": &topic,
+            "subscribers": Self::subscribers(&topic),
+        }))
+        .into_response()
+    }
+
+    /// `GET {path}/admin/sockets/{id}`
+    pub(crate) async fn admin_socket(
+        headers: HeaderMap,
+        Path(id): Path<String>,
+        Extension(websocket): Extension<Arc<WebSocket<T>>>,
+    ) -> Response {
+        if let Err(res) = websocket.authorize_admin(headers).await {
+            return res;
+        }
+
+        Json(Self::connections_of(&id)).into_response()
+    }
+
+    /// `GET {path}/admin/connections/{connection_id}`
+    pub(crate) async fn admin_connection(
+        headers: HeaderMap,
+        Path(id): Path<String>,
+        Extension(websocket): Extension<Arc<WebSocket<T>>>,
+    ) -> Response {
+        if let Err(res) = websocket.authorize_admin(headers).await {
+            return res;
+        }
+
+        match Self::connection(&id) {
+            Some(connection) => Json(connection).into_response(),
+            None => StatusCode::NOT_FOUND.into_response(),
+        }
+    }
+
+    async fn authorize_admin(&self, headers: HeaderMap) -> Result<(), Response> {
+        let Some(authorize) = self.admin.as_ref() else {
+            return Err(StatusCode::NOT_FOUND.into_response());
+        };
+
+        let res = authorize.call(headers).await;
+
+        if res.status().is_client_error() || res.status().is_server_error() {
+            return Err(res);
+        }
+
+        Ok(())
+    }
+}
+
+fn unix_millis<S: Serializer>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+    let millis = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as u64);
+
+    serializer.serialize_u64(millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_util::{fixture::join, ChannelTest},
+        Channel,
+    };
+
+    #[derive(Default)]
+    struct Endpoint;
+
+    #[tokio::test]
+    async fn introspection_should_list_topics_and_connections() {
+        let websocket =
+            WebSocket::<Endpoint>::new("/introspect").channel("room:*", Channel::new().join(join));
+        let test = ChannelTest::new(websocket);
+
+        let alice = test.connect(json!({})).await.unwrap();
+        let bob = test.connect(json!({})).await.unwrap();
+        let (_, _lobby) = alice
+            .subscribe_and_join("room:lobby", json!({}))
+            .await
+            .unwrap();
+        let (_, _game) = alice
+            .subscribe_and_join("room:game", json!({}))
+            .await
+            .unwrap();
+        let (_, _bob) = bob
+            .subscribe_and_join("room:lobby", json!({}))
+            .await
+            .unwrap();
+
+        let alice_id = alice.socket().lock().await.id.clone();
+        let bob_id = bob.socket().lock().await.id.clone();
+        let alice_connection = alice.socket().lock().await.connection_id.clone();
+
+        assert_eq!(WebSocket::<Endpoint>::connection_count(), 2);
+        assert_eq!(
+            WebSocket::<Endpoint>::topics(),
+            vec![
+                TopicInfo {
+                    topic: "room:game".into(),
+                    subscribers: 1
+                },
+                TopicInfo {
+                    topic: "room:lobby".into(),
+                    subscribers: 2
+                },
+            ]
+        );
+
+        let mut lobby = vec![alice_id.clone(), bob_id.clone()];
+        lobby.sort();
+        assert_eq!(WebSocket::<Endpoint>::subscribers("room:lobby"), lobby);
+
+        let connection = WebSocket::<Endpoint>::connection(&alice_connection).unwrap();
+        assert_eq!(connection.id, alice_id);
+        assert_eq!(connection.vsn, Some("2.0.0"));
+        assert_eq!(
+            connection.topics,
+            vec![Topic::from("room:game"), Topic::from("room:lobby")]
+        );
+
+        drop(bob);
+        drop(_bob);
+        assert_eq!(WebSocket::<Endpoint>::connection_count(), 1);
+        assert!(WebSocket::<Endpoint>::connection(&bob_id).is_none());
+        assert_eq!(
+            WebSocket::<Endpoint>::subscribers("room:lobby"),
+            vec![alice_id]
+        );
+    }
+}
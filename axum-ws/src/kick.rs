@@ -0,0 +1,159 @@
+use crate::{
+    message::Message, topic::Topic, user_id::UserId, websocket::WebSocket,
+    websocket_state::WEBSOCKET_STATE,
+};
+use serde_json::json;
+
+impl<T> WebSocket<T>
+where
+    T: Default + Send + Sync + 'static,
+{
+    /// Remove the connections with socket id `socket_id` from `topic` without
+    /// closing them, returning how many had joined it.
+    ///
+    /// Each runs the channel's `terminate` callback and gets a `phx_close` on the
+    /// topic with `{"reason": reason}`, so the client stops rejoining it. Pair it
+    /// with [`WebSocket::ban`] to refuse a join the client makes on its own.
+    pub async fn kick(socket_id: &str, topic: &str, reason: &str) -> usize {
+        let Some(path) = WEBSOCKET_STATE.get_path::<T>() else {
+            return 0;
+        };
+
+        let topic: Topic = topic.into();
+        let mut kicked = 0;
+
+        for entry in WEBSOCKET_STATE.get_connections_on(&path, socket_id) {
+            if Self::kick_connection(&path, &entry, &topic, reason).await {
+                kicked += 1;
+            }
+        }
+
+        kicked
+    }
+
+    async fn kick_connection(path: &str, entry: &UserId, topic: &Topic, reason: &str) -> bool {
+        let Some(subscription) = WEBSOCKET_STATE.take_subscription(entry, topic) else {
+            return false;
+        };
+
+        WEBSOCKET_STATE.remove_user(&(path.to_string(), topic.clone()), entry);
+
+        tracing::info!(path, connection_id = %entry.as_str(), topic = %&**topic, reason, "channel kicked");
+
+        if let Some(tx) = WEBSOCKET_STATE.get_sender(entry) {
+            let join_ref = subscription
+                .snapshot
+                .message
+                .as_ref()
+                .and_then(|m| m.join_ref.clone());
+            let message = Message {
+                message_ref: join_ref.clone(),
+                join_ref,
+                topic: topic.clone(),
+                event: "phx_close".into(),
+                payload: json!({"reason": reason}).into(),
+            };
+
+            let _ = tx.send(&message).await;
+        }
+
+        if let Some(terminate) = subscription
+            .channel
+            .as_ref()
+            .and_then(|channel| channel.terminate.as_ref())
+        {
+            terminate.call(topic.clone(), subscription.socket).await;
+        }
+
+        true
+    }
+
+    /// Refuse joins of `topic` from socket id `socket_id` until [`WebSocket::unban`],
+    /// replying with an error before the channel's `join` runs.
+    ///
+    /// Connections that already joined stay until kicked.
+    pub fn ban(socket_id: &str, topic: &str) {
+        if let Some(path) = WEBSOCKET_STATE.get_path::<T>() {
+            WEBSOCKET_STATE.insert_ban((path, topic.into()), socket_id);
+        }
+    }
+
+    /// Lift a [`WebSocket::ban`], returning whether there was one.
+    pub fn unban(socket_id: &str, topic: &str) -> bool {
+        WEBSOCKET_STATE
+            .get_path::<T>()
+            .is_some_and(|path| WEBSOCKET_STATE.remove_ban(&(path, topic.into()), socket_id))
+    }
+
+    pub fn is_banned(socket_id: &str, topic: &str) -> bool {
+        WEBSOCKET_STATE
+            .get_path::<T>()
+            .is_some_and(|path| WEBSOCKET_STATE.is_banned(&(path, topic.into()), socket_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_util::{fixture::join, ChannelTest},
+        Channel, Socket,
+    };
+    use serde_json::Value;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static TERMINATED: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Default)]
+    struct Endpoint;
+
+    #[tokio::test]
+    async fn kick_should_close_the_channel_and_ban_should_refuse_joins() {
+        async fn connect(params: Value, socket: Socket) {
+            let user = params["user"].as_str().unwrap_or_default().to_string();
+            socket.lock().await.assigns.insert("user", user);
+        }
+
+        async fn id(socket: Socket) -> Option<String> {
+            socket.lock().await.assigns.get::<String>("user").cloned()
+        }
+
+        async fn terminate(_topic: Topic, _socket: Socket) {
+            TERMINATED.fetch_add(1, Ordering::SeqCst);
+        }
+
+        let websocket = WebSocket::<Endpoint>::new("/kick")
+            .connect(connect)
+            .id(id)
+            .channel("room:*", Channel::new().join(join).terminate(terminate));
+        let test = ChannelTest::new(websocket);
+        let socket = test.connect(json!({"user": "mallory"})).await.unwrap();
+        let (_, channel) = socket
+            .subscribe_and_join("room:1", json!({}))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            WebSocket::<Endpoint>::kick("mallory", "room:1", "spam").await,
+            1
+        );
+        assert_eq!(channel.assert_push("phx_close"), json!({"reason": "spam"}));
+        assert_eq!(TERMINATED.load(Ordering::SeqCst), 1);
+        assert!(WebSocket::<Endpoint>::subscribers("room:1").is_empty());
+        assert_eq!(
+            WebSocket::<Endpoint>::kick("mallory", "room:1", "spam").await,
+            0
+        );
+
+        // Leaving after the kick must not terminate the channel again.
+        channel.leave().await;
+        assert_eq!(TERMINATED.load(Ordering::SeqCst), 1);
+
+        WebSocket::<Endpoint>::ban("mallory", "room:1");
+        let res = socket.subscribe_and_join("room:1", json!({})).await;
+        assert_eq!(res.err(), Some(json!("banned")));
+
+        assert!(WebSocket::<Endpoint>::unban("mallory", "room:1"));
+        assert!(socket.subscribe_and_join("room:1", json!({})).await.is_ok());
+    }
+}
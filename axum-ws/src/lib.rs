@@ -2,21 +2,57 @@ use std::sync::Arc;
 use tokio::sync::Mutex;
 
 mod assigns;
+mod broadcast;
 mod channel;
+mod channel_handler;
+#[cfg(feature = "client")]
+pub mod client;
+mod connection;
 mod event;
 mod handler;
+mod introspect;
+mod kick;
+mod longpoll;
 mod message;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+mod outbound;
 mod payload;
+mod publish;
+mod serializer;
+mod shutdown;
 mod socket;
+mod sse;
+mod stateful_channel;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
 mod topic;
+mod topic_params;
+mod topic_router;
+mod transport;
 mod user_id;
 mod websocket;
 mod websocket_error;
 mod websocket_state;
 
+pub use assigns::Assigns;
+pub use async_trait::async_trait;
+pub use broadcast::BroadcastReport;
 pub use channel::Channel;
+pub use channel_handler::ChannelHandler;
+pub use introspect::{ConnectionInfo, TopicInfo};
+pub use longpoll::LongPoll;
+pub use outbound::OverflowPolicy;
 pub use payload::Payload;
+pub use shutdown::GracefulShutdown;
+pub use sse::ServerSentEvents;
+pub use stateful_channel::{StateHandler, StatefulChannel};
 pub use topic::Topic;
+pub use topic_params::{ParamsError, TopicParams};
+pub use transport::{
+    Accepted, CloseFrame, MemoryTransport, Transport, TransportMessage, TransportSink,
+    TransportStream,
+};
 pub use websocket::WebSocket;
 
 pub type Socket = Arc<Mutex<socket::Socket>>;
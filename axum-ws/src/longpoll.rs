@@ -0,0 +1,339 @@
+use crate::{
+    connection::Connection, outbound::OutboundReceiver, serializer::Serializer,
+    websocket::WebSocket, websocket_state::WEBSOCKET_STATE,
+};
+use axum::{
+    extract::Query,
+    http::{header, HeaderMap},
+    Extension, Json,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use dashmap::DashMap;
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+
+/// Settings of the Phoenix LongPoll fallback transport, mounted at `{path}/longpoll`.
+///
+/// phoenix.js switches to it when WebSocket upgrades are blocked. Each client gets
+/// a server-side session identified by a signed token, polls it with `GET` and
+/// sends batches of messages with `POST`, driving the same channels as a WebSocket.
+#[derive(Clone)]
+pub struct LongPoll {
+    poll_timeout: Duration,
+    window: Duration,
+    secret: Arc<[u8]>,
+}
+
+impl Default for LongPoll {
+    fn default() -> Self {
+        Self {
+            poll_timeout: Duration::from_secs(10),
+            window: Duration::from_secs(10),
+            secret: nanoid::nanoid!(64).into_bytes().into(),
+        }
+    }
+}
+
+impl LongPoll {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How long a poll waits for messages before answering empty, defaults to 10 seconds.
+    pub fn poll_timeout(mut self, poll_timeout: Duration) -> Self {
+        self.poll_timeout = poll_timeout;
+        self
+    }
+
+    /// How long a session survives without being polled, defaults to 10 seconds.
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Key used to sign session tokens, a random one is generated per process by default.
+    pub fn secret(mut self, secret: impl AsRef<[u8]>) -> Self {
+        self.secret = secret.as_ref().into();
+        self
+    }
+
+    fn sign(&self, session_id: &str) -> String {
+        let mut mac = self.mac();
+        mac.update(session_id.as_bytes());
+        let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+
+        format!("{session_id}.{signature}")
+    }
+
+    fn verify<'a>(&self, token: &'a str) -> Option<&'a str> {
+        let (session_id, signature) = token.split_once('.')?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+
+        let mut mac = self.mac();
+        mac.update(session_id.as_bytes());
+        mac.verify_slice(&signature).ok()?;
+
+        Some(session_id)
+    }
+
+    fn mac(&self) -> Hmac<Sha256> {
+        Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts keys of any size")
+    }
+}
+
+pub(crate) struct Session<T> {
+    connection: Mutex<Connection<T>>,
+    receiver: Mutex<OutboundReceiver>,
+    last_poll: std::sync::Mutex<Instant>,
+    polling: AtomicUsize,
+}
+
+pub(crate) type Sessions<T> = DashMap<String, Arc<Session<T>>>;
+
+impl<T> Session<T> {
+    fn touch(&self) {
+        *self.last_poll.lock().unwrap() = Instant::now();
+    }
+
+    fn is_expired(&self, window: Duration) -> bool {
+        self.polling.load(Ordering::Acquire) == 0
+            && self.last_poll.lock().unwrap().elapsed() >= window
+    }
+
+    /// Count a poll in until the guard is dropped, which also happens when the
+    /// client goes away and axum cancels the request.
+    fn poll(&self) -> Polling<'_, T> {
+        self.polling.fetch_add(1, Ordering::AcqRel);
+        Polling(self)
+    }
+}
+
+/// An open poll of a session, see [`Session::poll`].
+struct Polling<'a, T>(&'a Session<T>);
+
+impl<T> Drop for Polling<'_, T> {
+    fn drop(&mut self) {
+        self.0.touch();
+        self.0.polling.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+impl<T> WebSocket<T>
+where
+    T: Default + Send + Sync + 'static,
+{
+    /// `GET {path}/longpoll`: open a session, or wait for messages on an existing one.
+    pub(crate) async fn longpoll_get(
+        Query(params): Query<Value>,
+        Extension(websocket): Extension<Arc<WebSocket<T>>>,
+    ) -> Json<Value> {
+        let Some(longpoll) = websocket.longpoll.as_ref() else {
+            return Json(json!({"status": 404}));
+        };
+
+        let token = params.get("token").and_then(Value::as_str);
+        let session_id = token.and_then(|token| longpoll.verify(token));
+        let session = session_id
+            .and_then(|session_id| websocket.sessions.get(session_id))
+            .map(|session| session.value().clone());
+
+        let draining = WEBSOCKET_STATE
+            .get_shutdown(&websocket.path)
+            .is_some_and(|shutdown| shutdown.is_draining());
+
+        // Phoenix's client reconnects on 410, hopefully to a node that is not shutting down.
+        if let (true, Some(session_id), Some(session)) = (draining, session_id, session.as_ref()) {
+            websocket.sessions.remove(session_id);
+            session.connection.lock().await.terminate().await;
+            session.connection.lock().await.close("shutdown").await;
+            return Json(json!({"status": 410}));
+        }
+
+        let (Some(token), Some(session)) = (token, session) else {
+            return match Self::new_session(websocket.clone(), params).await {
+                Some(token) => Json(json!({"status": 410, "token": token})),
+                None => Json(json!({"status": 403})),
+            };
+        };
+
+        let polling = session.poll();
+        let mut receiver = session.receiver.lock().await;
+
+        let res = match tokio::time::timeout(longpoll.poll_timeout, receiver.recv()).await {
+            Ok(Some(frame)) => {
+                let mut messages = vec![Value::from(frame.as_str())];
+
+                while let Some(frame) = receiver.try_recv() {
+                    messages.push(frame.as_str().into());
+                }
+
+                json!({"status": 200, "token": token, "messages": messages})
+            }
+            Ok(None) => json!({"status": 410}),
+            Err(_) => json!({"status": 204, "token": token}),
+        };
+
+        drop(receiver);
+        drop(polling);
+
+        Json(res)
+    }
+
+    /// `POST {path}/longpoll`: dispatch a message, or a newline-delimited batch of them.
+    pub(crate) async fn longpoll_post(
+        Query(params): Query<Value>,
+        headers: HeaderMap,
+        Extension(websocket): Extension<Arc<WebSocket<T>>>,
+        body: String,
+    ) -> Json<Value> {
+        let session = websocket
+            .longpoll
+            .as_ref()
+            .zip(params.get("token").and_then(Value::as_str))
+            .and_then(|(longpoll, token)| longpoll.verify(token))
+            .and_then(|session_id| websocket.sessions.get(session_id))
+            .map(|session| session.value().clone());
+
+        let Some(session) = session else {
+            return Json(json!({"status": 410}));
+        };
+
+        let batched = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("application/x-ndjson"));
+
+        let mut connection = session.connection.lock().await;
+        let messages = if batched {
+            body.lines().filter(|line| !line.is_empty()).collect()
+        } else {
+            vec![body.as_str()]
+        };
+
+        for message in messages {
+            if connection.handle_text(message).await.is_err() {
+                return Json(json!({"status": 400}));
+            }
+        }
+
+        Json(json!({"status": 200}))
+    }
+
+    async fn new_session(websocket: Arc<WebSocket<T>>, params: Value) -> Option<String> {
+        let longpoll = websocket.longpoll.clone()?;
+        let serializer = Serializer::from_vsn(params.get("vsn").and_then(Value::as_str));
+        let socket = Connection::authenticate(&websocket, params).await.ok()?;
+        let (connection, receiver) = Connection::open(websocket.clone(), socket, serializer).await;
+
+        let session_id = nanoid::nanoid!();
+        let session = Arc::new(Session {
+            connection: Mutex::new(connection),
+            receiver: Mutex::new(receiver),
+            last_poll: std::sync::Mutex::new(Instant::now()),
+            polling: AtomicUsize::new(0),
+        });
+
+        websocket
+            .sessions
+            .insert(session_id.clone(), session.clone());
+
+        // Phoenix closes a session once its client stops polling, do the same here.
+        tokio::spawn({
+            let session_id = session_id.clone();
+
+            async move {
+                loop {
+                    tokio::time::sleep(longpoll.window).await;
+
+                    if session.is_expired(longpoll.window) {
+                        break;
+                    }
+                }
+
+                websocket.sessions.remove(&session_id);
+                let mut connection = session.connection.lock().await;
+                connection.terminate().await;
+                connection.close("timeout").await;
+            }
+        });
+
+        Some(longpoll.sign(&session_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        channel::Channel,
+        test_util::fixture::{join, Endpoint},
+        Socket, Topic,
+    };
+
+    #[test]
+    fn longpoll_token_should_be_verified() {
+        let longpoll = LongPoll::new().secret("secret");
+        let token = longpoll.sign("session");
+
+        assert_eq!(longpoll.verify(&token), Some("session"));
+        assert_eq!(longpoll.verify("session"), None);
+        assert_eq!(longpoll.verify(&token.replace("session", "other")), None);
+        assert_eq!(LongPoll::new().secret("other").verify(&token), None);
+    }
+
+    #[tokio::test]
+    async fn longpoll_session_should_expire_after_a_dropped_poll() {
+        static TERMINATED: AtomicUsize = AtomicUsize::new(0);
+
+        async fn terminate(_topic: Topic, _socket: Socket) {
+            TERMINATED.fetch_add(1, Ordering::SeqCst);
+        }
+
+        let longpoll = LongPoll::new()
+            .window(Duration::from_millis(50))
+            .poll_timeout(Duration::from_secs(60));
+        let websocket = Arc::new(
+            WebSocket::<Endpoint>::new("/longpoll_expiry")
+                .longpoll(longpoll)
+                .channel("room:*", Channel::new().join(join).terminate(terminate)),
+        );
+        let get = |token: Option<&str>| {
+            let params = json!({"token": token, "vsn": "2.0.0"});
+            WebSocket::<Endpoint>::longpoll_get(Query(params), Extension(websocket.clone()))
+        };
+
+        let Json(res) = get(None).await;
+        let token = res["token"].as_str().unwrap().to_string();
+        assert_eq!(websocket.sessions.len(), 1);
+
+        let join = json!(["1", "1", "room:1", "phx_join", {}]).to_string();
+        let params = json!({"token": token, "vsn": "2.0.0"});
+        let Json(res) = WebSocket::<Endpoint>::longpoll_post(
+            Query(params),
+            HeaderMap::new(),
+            Extension(websocket.clone()),
+            join,
+        )
+        .await;
+        assert_eq!(res["status"], 200);
+        let Json(res) = get(Some(&token)).await;
+        assert_eq!(res["status"], 200);
+
+        // The client goes away while its poll is pending, so axum drops the handler.
+        let poll = tokio::time::timeout(Duration::from_millis(20), get(Some(&token))).await;
+        assert!(poll.is_err());
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(websocket.sessions.is_empty());
+        assert_eq!(TERMINATED.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,439 @@
+//! Aggregated numbers about every endpoint, rendered in the Prometheus text format.
+//!
+//! The registry is process wide and filled by the engine itself, so there is
+//! nothing to set up: mount [`WebSocket::metrics`] or serve [`render`] from a
+//! route of your own.
+//!
+//! Inbound events are labelled by name only for the protocol's own and those
+//! with a `handler`, any other event a client sends is counted as `other`.
+
+use crate::{outbound::OverflowPolicy, websocket::WebSocket, websocket_state::WEBSOCKET_STATE};
+use axum::{http::header, response::IntoResponse};
+use dashmap::DashMap;
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    hash::Hash,
+    sync::{
+        atomic::{AtomicI64, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+lazy_static::lazy_static!(
+    pub(crate) static ref METRICS: Metrics = Metrics::default();
+);
+
+const FANOUT_BUCKETS: &[f64] = &[0.0, 1.0, 5.0, 10.0, 50.0, 100.0, 500.0, 1000.0, 5000.0];
+
+const LATENCY_BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+/// The `event` label of events a client may make up, so that it cannot grow the
+/// label set without bound.
+pub(crate) const OTHER_EVENT: &str = "other";
+
+#[derive(Default)]
+pub(crate) struct Metrics {
+    connections: DashMap<String, Arc<AtomicI64>>,
+    channels: DashMap<(String, String), Arc<AtomicI64>>,
+    messages_in: DashMap<String, AtomicU64>,
+    messages_out: DashMap<String, AtomicU64>,
+    dropped: DashMap<String, AtomicU64>,
+    fanout: DashMap<String, Histogram>,
+    handler_duration: DashMap<(String, String), Histogram>,
+}
+
+/// Keeps a gauge incremented for as long as it is alive.
+pub(crate) struct Tracked(Arc<AtomicI64>);
+
+impl Tracked {
+    fn new(gauge: Arc<AtomicI64>) -> Self {
+        gauge.fetch_add(1, Ordering::Relaxed);
+        Self(gauge)
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Metrics {
+    /// Count a connection to `path` until the returned guard is dropped.
+    pub(crate) fn connection(&self, path: &str) -> Tracked {
+        Tracked::new(
+            self.connections
+                .entry(path.to_string())
+                .or_default()
+                .clone(),
+        )
+    }
+
+    /// Count a channel joined through `pattern` until the returned guard is dropped.
+    pub(crate) fn channel(&self, path: &str, pattern: &str) -> Tracked {
+        let key = (path.to_string(), pattern.to_string());
+        Tracked::new(self.channels.entry(key).or_default().clone())
+    }
+
+    pub(crate) fn message_in(&self, event: &str) {
+        increment(&self.messages_in, event, 1);
+    }
+
+    pub(crate) fn messages_out(&self, event: &str, count: usize) {
+        increment(&self.messages_out, event, count as u64);
+    }
+
+    pub(crate) fn dropped(&self, policy: OverflowPolicy) {
+        let policy = match policy {
+            OverflowPolicy::Block => "block",
+            OverflowPolicy::DropOldest => "drop_oldest",
+            OverflowPolicy::DropNewest => "drop_newest",
+            OverflowPolicy::Disconnect => "disconnect",
+        };
+
+        increment(&self.dropped, policy, 1);
+    }
+
+    pub(crate) fn fanout(&self, path: &str, recipients: usize) {
+        observe(&self.fanout, path, FANOUT_BUCKETS, recipients as f64);
+    }
+
+    pub(crate) fn handler_duration(&self, path: &str, event: &str, duration: Duration) {
+        let key = (path.to_string(), event.to_string());
+        observe(
+            &self.handler_duration,
+            &key,
+            LATENCY_BUCKETS,
+            duration.as_secs_f64(),
+        );
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "connections",
+            "gauge",
+            "Open connections per endpoint.",
+        );
+        for (path, value) in sorted(&self.connections) {
+            sample(
+                &mut out,
+                "connections",
+                &[("path", &path)],
+                value.load(Ordering::Relaxed),
+            );
+        }
+
+        header(
+            &mut out,
+            "channels",
+            "gauge",
+            "Joined channels per topic pattern.",
+        );
+        for ((path, pattern), value) in sorted(&self.channels) {
+            let labels = [("path", path.as_str()), ("pattern", &pattern)];
+            sample(&mut out, "channels", &labels, value.load(Ordering::Relaxed));
+        }
+
+        header(
+            &mut out,
+            "messages_in_total",
+            "counter",
+            "Messages received per event.",
+        );
+        for (event, value) in sorted(&self.messages_in) {
+            let value = value.load(Ordering::Relaxed);
+            sample(&mut out, "messages_in_total", &[("event", &event)], value);
+        }
+
+        header(
+            &mut out,
+            "messages_out_total",
+            "counter",
+            "Messages queued per event.",
+        );
+        for (event, value) in sorted(&self.messages_out) {
+            let value = value.load(Ordering::Relaxed);
+            sample(&mut out, "messages_out_total", &[("event", &event)], value);
+        }
+
+        header(
+            &mut out,
+            "dropped_messages_total",
+            "counter",
+            "Messages discarded by the overflow policy.",
+        );
+        for (policy, value) in sorted(&self.dropped) {
+            let value = value.load(Ordering::Relaxed);
+            sample(
+                &mut out,
+                "dropped_messages_total",
+                &[("policy", &policy)],
+                value,
+            );
+        }
+
+        header(
+            &mut out,
+            "outbound_queue_depth",
+            "gauge",
+            "Messages waiting in outbound queues.",
+        );
+        sample(
+            &mut out,
+            "outbound_queue_depth",
+            &[],
+            WEBSOCKET_STATE.queued(),
+        );
+
+        header(
+            &mut out,
+            "broadcast_recipients",
+            "histogram",
+            "Broadcast fan-out size.",
+        );
+        for (path, histogram) in sorted(&self.fanout) {
+            histogram.render(&mut out, "broadcast_recipients", &[("path", &path)]);
+        }
+
+        header(
+            &mut out,
+            "handler_duration_seconds",
+            "histogram",
+            "Time spent in join and handle_in callbacks.",
+        );
+        for ((path, event), histogram) in sorted(&self.handler_duration) {
+            let labels = [("path", path.as_str()), ("event", &event)];
+            histogram.render(&mut out, "handler_duration_seconds", &labels);
+        }
+
+        out
+    }
+}
+
+/// Render every metric in the Prometheus text exposition format.
+pub fn render() -> String {
+    METRICS.render()
+}
+
+impl<T> WebSocket<T>
+where
+    T: Default + Send + Sync + 'static,
+{
+    /// Serve the metrics of every endpoint at `GET {path}/metrics`, see [`render`].
+    pub fn metrics(mut self) -> Self {
+        self.metrics = true;
+        self
+    }
+
+    pub(crate) async fn metrics_handler() -> impl IntoResponse {
+        (
+            [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+            render(),
+        )
+    }
+}
+
+struct Histogram {
+    bounds: &'static [f64],
+    buckets: Vec<AtomicU64>,
+    count: AtomicU64,
+    sum: AtomicU64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            buckets: bounds.iter().map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0f64.to_bits()),
+        }
+    }
+
+    fn observe(&self, value: f64) {
+        if let Some(i) = self.bounds.iter().position(|bound| value <= *bound) {
+            self.buckets[i].fetch_add(1, Ordering::Relaxed);
+        }
+
+        self.count.fetch_add(1, Ordering::Relaxed);
+        let _ = self
+            .sum
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |sum| {
+                Some((f64::from_bits(sum) + value).to_bits())
+            });
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &[(&str, &str)]) {
+        let mut cumulative = 0;
+
+        for (bound, bucket) in self.bounds.iter().zip(&self.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let le = bound.to_string();
+            let labels = [labels, &[("le", le.as_str())]].concat();
+            sample(out, &format!("{name}_bucket"), &labels, cumulative);
+        }
+
+        let count = self.count.load(Ordering::Relaxed);
+        let labels_inf = [labels, &[("le", "+Inf")]].concat();
+        sample(out, &format!("{name}_bucket"), &labels_inf, count);
+        sample(
+            out,
+            &format!("{name}_sum"),
+            labels,
+            f64::from_bits(self.sum.load(Ordering::Relaxed)),
+        );
+        sample(out, &format!("{name}_count"), labels, count);
+    }
+}
+
+fn increment<K, Q>(map: &DashMap<K, AtomicU64>, key: &Q, by: u64)
+where
+    K: Eq + Hash + std::borrow::Borrow<Q>,
+    Q: ?Sized + Eq + Hash + ToOwned<Owned = K>,
+{
+    if let Some(counter) = map.get(key) {
+        counter.fetch_add(by, Ordering::Relaxed);
+        return;
+    }
+
+    map.entry(key.to_owned())
+        .or_default()
+        .fetch_add(by, Ordering::Relaxed);
+}
+
+fn observe<K, Q>(map: &DashMap<K, Histogram>, key: &Q, bounds: &'static [f64], value: f64)
+where
+    K: Eq + Hash + std::borrow::Borrow<Q>,
+    Q: ?Sized + Eq + Hash + ToOwned<Owned = K>,
+{
+    if let Some(histogram) = map.get(key) {
+        histogram.observe(value);
+        return;
+    }
+
+    map.entry(key.to_owned())
+        .or_insert_with(|| Histogram::new(bounds))
+        .observe(value);
+}
+
+/// Snapshot of a map in label order, so scrapes are stable.
+fn sorted<K, V>(map: &DashMap<K, V>) -> BTreeMap<K, dashmap::mapref::multiple::RefMulti<'_, K, V>>
+where
+    K: Eq + Hash + Ord + Clone,
+{
+    map.iter()
+        .map(|entry| (entry.key().clone(), entry))
+        .collect()
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP axum_ws_{name} {help}");
+    let _ = writeln!(out, "# TYPE axum_ws_{name} {kind}");
+}
+
+fn sample(out: &mut String, name: &str, labels: &[(&str, &str)], value: impl std::fmt::Display) {
+    let _ = write!(out, "axum_ws_{name}");
+
+    if !labels.is_empty() {
+        let labels = labels
+            .iter()
+            .map(|(key, value)| format!("{key}=\"{}\"", escape(value)))
+            .collect::<Vec<_>>();
+        let _ = write!(out, "{{{}}}", labels.join(","));
+    }
+
+    let _ = writeln!(out, " {value}");
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        handler::Response,
+        test_util::fixture::{connect, join, Endpoint},
+        Channel, Payload, Socket,
+    };
+    use serde_json::{json, Value};
+
+    #[tokio::test]
+    async fn metrics_should_track_connections_and_channels() {
+        async fn ping(_payload: Payload, _socket: Socket) -> anyhow::Result<Value> {
+            Ok(json!({}))
+        }
+
+        let websocket = WebSocket::<Endpoint>::new("/metrics").channel(
+            "room:*",
+            Channel::new()
+                .join(join)
+                .handler("ping", ping)
+                .handle_in(|_event, _payload, _socket| async { Response::NoReply }),
+        );
+        let socket = connect(websocket).await;
+        let (_, channel) = socket
+            .subscribe_and_join("room:1", json!({}))
+            .await
+            .unwrap();
+
+        let reference = channel.push("ping", json!({})).await;
+        channel.assert_reply(&reference, "ok");
+
+        let metrics = render();
+        assert!(metrics.contains("axum_ws_connections{path=\"/metrics\"} 1\n"));
+        assert!(metrics.contains("axum_ws_channels{path=\"/metrics\",pattern=\"room:*\"} 1\n"));
+        assert!(metrics.contains(
+            "axum_ws_handler_duration_seconds_count{path=\"/metrics\",event=\"ping\"} 1\n"
+        ));
+
+        // Events made up by the client share one label.
+        channel.push("made_up", json!({})).await;
+        channel.push("made_up_too", json!({})).await;
+
+        let metrics = render();
+        assert!(metrics.contains("axum_ws_messages_in_total{event=\"other\"}"));
+        assert!(metrics.contains(
+            "axum_ws_handler_duration_seconds_count{path=\"/metrics\",event=\"other\"} 2\n"
+        ));
+        assert!(!metrics.contains("made_up"));
+
+        drop(channel);
+        drop(socket);
+
+        let metrics = render();
+        assert!(metrics.contains("axum_ws_connections{path=\"/metrics\"} 0\n"));
+        assert!(metrics.contains("axum_ws_channels{path=\"/metrics\",pattern=\"room:*\"} 0\n"));
+    }
+
+    #[test]
+    fn histogram_should_render_cumulative_buckets() {
+        let histogram = Histogram::new(&[1.0, 5.0]);
+        histogram.observe(1.0);
+        histogram.observe(3.0);
+        histogram.observe(10.0);
+
+        let mut out = String::new();
+        histogram.render(&mut out, "test", &[("path", "/a\"b")]);
+
+        assert_eq!(
+            out,
+            "axum_ws_test_bucket{path=\"/a\\\"b\",le=\"1\"} 1\n\
+             axum_ws_test_bucket{path=\"/a\\\"b\",le=\"5\"} 2\n\
+             axum_ws_test_bucket{path=\"/a\\\"b\",le=\"+Inf\"} 3\n\
+             axum_ws_test_sum{path=\"/a\\\"b\"} 14\n\
+             axum_ws_test_count{path=\"/a\\\"b\"} 3\n"
+        );
+    }
+}
@@ -0,0 +1,364 @@
+use crate::{
+    message::Message,
+    serializer::{Frame, Serializer},
+    websocket_error::WebSocketError,
+};
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::sync::Notify;
+
+#[cfg(feature = "metrics")]
+use crate::metrics::METRICS;
+
+pub(crate) const DEFAULT_BUFFER_SIZE: usize = 1024;
+pub(crate) const DEFAULT_BLOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What to do when a connection's outbound queue is full.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wait until the client has drained enough of the queue.
+    ///
+    /// Broadcasts first deliver to every subscriber with room in its queue and
+    /// only then wait for the full ones, so a slow client never delays the others.
+    /// A broadcast gives up on a client after the block timeout and drops the
+    /// message for it, see [`WebSocket::block_timeout`](crate::WebSocket::block_timeout).
+    #[default]
+    Block,
+    /// Discard the oldest queued message to make room for the new one.
+    DropOldest,
+    /// Discard the new message and keep the queue as it is.
+    DropNewest,
+    /// Close the connection of the slow consumer.
+    Disconnect,
+}
+
+/// Outcome of offering a message to an outbound queue without waiting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Offer {
+    Queued,
+    /// Queued after evicting the oldest frame under [`OverflowPolicy::DropOldest`].
+    Replaced,
+    Dropped,
+    Full,
+    /// The queue was full and the connection got closed under [`OverflowPolicy::Disconnect`].
+    Overflowed,
+    Closed,
+}
+
+struct Shared {
+    queue: Mutex<VecDeque<Frame>>,
+    serializer: Serializer,
+    capacity: usize,
+    policy: OverflowPolicy,
+    block_timeout: Duration,
+    closed: AtomicBool,
+    overflowed: AtomicBool,
+    readable: Notify,
+    writable: Notify,
+}
+
+#[derive(Clone)]
+pub(crate) struct OutboundSender {
+    shared: Arc<Shared>,
+}
+
+pub(crate) struct OutboundReceiver {
+    shared: Arc<Shared>,
+}
+
+pub(crate) fn channel(
+    serializer: Serializer,
+    capacity: usize,
+    policy: OverflowPolicy,
+    block_timeout: Duration,
+) -> (OutboundSender, OutboundReceiver) {
+    let shared = Arc::new(Shared {
+        queue: Mutex::new(VecDeque::with_capacity(capacity.min(DEFAULT_BUFFER_SIZE))),
+        serializer,
+        capacity: capacity.max(1),
+        policy,
+        block_timeout,
+        closed: AtomicBool::new(false),
+        overflowed: AtomicBool::new(false),
+        readable: Notify::new(),
+        writable: Notify::new(),
+    });
+
+    (
+        OutboundSender {
+            shared: shared.clone(),
+        },
+        OutboundReceiver { shared },
+    )
+}
+
+impl Offer {
+    /// Whether the frame made it into the queue.
+    pub(crate) fn is_queued(&self) -> bool {
+        matches!(self, Offer::Queued | Offer::Replaced)
+    }
+}
+
+impl OutboundSender {
+    pub(crate) fn serializer(&self) -> Serializer {
+        self.shared.serializer
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.shared.closed.load(Ordering::Acquire)
+    }
+
+    /// Enqueue without waiting, applying the overflow policy when the queue is full.
+    ///
+    /// Under [`OverflowPolicy::Block`] a full queue yields [`Offer::Full`] and the
+    /// frame is handed back untouched so the caller can decide to wait.
+    pub(crate) fn offer(&self, frame: Frame) -> (Offer, Option<Frame>) {
+        let shared = &self.shared;
+
+        if self.is_closed() {
+            return (Offer::Closed, None);
+        }
+
+        let mut queue = shared.queue.lock().unwrap();
+
+        if queue.len() < shared.capacity {
+            queue.push_back(frame);
+            drop(queue);
+            shared.readable.notify_one();
+            return (Offer::Queued, None);
+        }
+
+        match shared.policy {
+            OverflowPolicy::Block => (Offer::Full, Some(frame)),
+            OverflowPolicy::DropOldest => {
+                #[cfg(feature = "metrics")]
+                METRICS.dropped(shared.policy);
+
+                queue.pop_front();
+                queue.push_back(frame);
+                drop(queue);
+                shared.readable.notify_one();
+                (Offer::Replaced, None)
+            }
+            OverflowPolicy::DropNewest => {
+                #[cfg(feature = "metrics")]
+                METRICS.dropped(shared.policy);
+
+                (Offer::Dropped, None)
+            }
+            OverflowPolicy::Disconnect => {
+                drop(queue);
+
+                #[cfg(feature = "metrics")]
+                METRICS.dropped(shared.policy);
+
+                shared.overflowed.store(true, Ordering::Release);
+                self.close();
+                (Offer::Overflowed, None)
+            }
+        }
+    }
+
+    /// Encode and enqueue a message addressed to this connection only.
+    pub(crate) async fn send(&self, message: &Message) -> Result<Offer, WebSocketError> {
+        let offer = self.send_frame(self.serializer().encode(message)).await?;
+
+        #[cfg(feature = "metrics")]
+        if offer.is_queued() {
+            METRICS.messages_out(&message.event.to_string(), 1);
+        }
+
+        Ok(offer)
+    }
+
+    /// Enqueue, waiting for room when the policy is [`OverflowPolicy::Block`].
+    pub(crate) async fn send_frame(&self, mut frame: Frame) -> Result<Offer, WebSocketError> {
+        loop {
+            let writable = self.shared.writable.notified();
+            tokio::pin!(writable);
+            writable.as_mut().enable();
+
+            match self.offer(frame) {
+                (Offer::Full, Some(f)) => {
+                    frame = f;
+                    writable.await;
+                }
+                (Offer::Closed | Offer::Overflowed, _) => {
+                    return Err(WebSocketError::ConnectionClosed)
+                }
+                (offer, _) => return Ok(offer),
+            }
+        }
+    }
+
+    /// Enqueue a broadcast, waiting at most the block timeout for room so that a
+    /// stalled client cannot hold up the broadcaster.
+    pub(crate) async fn send_broadcast(&self, frame: Frame) -> Offer {
+        match tokio::time::timeout(self.shared.block_timeout, self.send_frame(frame)).await {
+            Ok(res) => res.unwrap_or(Offer::Closed),
+            Err(_) => {
+                #[cfg(feature = "metrics")]
+                METRICS.dropped(self.shared.policy);
+
+                Offer::Dropped
+            }
+        }
+    }
+
+    /// Number of frames waiting to be written.
+    pub(crate) fn len(&self) -> usize {
+        self.shared.queue.lock().unwrap().len()
+    }
+
+    pub(crate) fn close(&self) {
+        self.shared.closed.store(true, Ordering::Release);
+        self.shared.readable.notify_one();
+        self.shared.writable.notify_waiters();
+    }
+}
+
+impl OutboundReceiver {
+    /// Next queued frame, or `None` once the sender side has been closed.
+    ///
+    /// A connection closed for overflowing stops immediately, anything else
+    /// drains what is left in the queue first.
+    pub(crate) async fn recv(&mut self) -> Option<Frame> {
+        let shared = &self.shared;
+
+        loop {
+            let readable = shared.readable.notified();
+            tokio::pin!(readable);
+            readable.as_mut().enable();
+
+            if self.overflowed() {
+                return None;
+            }
+
+            if let Some(frame) = shared.queue.lock().unwrap().pop_front() {
+                shared.writable.notify_one();
+                return Some(frame);
+            }
+
+            if shared.closed.load(Ordering::Acquire) {
+                return None;
+            }
+
+            readable.await;
+        }
+    }
+
+    /// Next queued frame if there is one, without waiting.
+    pub(crate) fn try_recv(&mut self) -> Option<Frame> {
+        let frame = self.shared.queue.lock().unwrap().pop_front()?;
+        self.shared.writable.notify_one();
+
+        Some(frame)
+    }
+
+    /// Whether the queue was closed by [`OverflowPolicy::Disconnect`].
+    pub(crate) fn overflowed(&self) -> bool {
+        self.shared.overflowed.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for OutboundReceiver {
+    fn drop(&mut self) {
+        self.shared.closed.store(true, Ordering::Release);
+        self.shared.writable.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn frame(event: &str) -> Frame {
+        Frame::from(event.to_string())
+    }
+
+    async fn drain(rx: &mut OutboundReceiver) -> Vec<String> {
+        let mut events = vec![];
+
+        while let Ok(Some(m)) = tokio::time::timeout(Duration::from_millis(10), rx.recv()).await {
+            events.push(m.to_string());
+        }
+
+        events
+    }
+
+    #[tokio::test]
+    async fn drop_oldest_should_keep_latest_messages() {
+        let (tx, mut rx) = channel(
+            Serializer::V2,
+            2,
+            OverflowPolicy::DropOldest,
+            DEFAULT_BLOCK_TIMEOUT,
+        );
+
+        assert_eq!(tx.offer(frame("a")).0, Offer::Queued);
+        assert_eq!(tx.offer(frame("b")).0, Offer::Queued);
+        assert_eq!(tx.offer(frame("c")).0, Offer::Replaced);
+
+        assert_eq!(drain(&mut rx).await, vec!["b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn drop_newest_should_keep_earliest_messages() {
+        let (tx, mut rx) = channel(
+            Serializer::V2,
+            2,
+            OverflowPolicy::DropNewest,
+            DEFAULT_BLOCK_TIMEOUT,
+        );
+
+        tx.offer(frame("a"));
+        tx.offer(frame("b"));
+        assert_eq!(tx.offer(frame("c")).0, Offer::Dropped);
+
+        assert_eq!(drain(&mut rx).await, vec!["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn disconnect_should_close_queue() {
+        let (tx, mut rx) = channel(
+            Serializer::V2,
+            1,
+            OverflowPolicy::Disconnect,
+            DEFAULT_BLOCK_TIMEOUT,
+        );
+
+        tx.offer(frame("a"));
+        assert_eq!(tx.offer(frame("b")).0, Offer::Overflowed);
+        assert!(rx.overflowed());
+        assert!(rx.recv().await.is_none());
+        assert!(tx.send_frame(frame("c")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn block_should_wait_for_room() {
+        let (tx, mut rx) = channel(
+            Serializer::V2,
+            1,
+            OverflowPolicy::Block,
+            DEFAULT_BLOCK_TIMEOUT,
+        );
+
+        tx.offer(frame("a"));
+        let (offer, frame_b) = tx.offer(frame("b"));
+        assert_eq!(offer, Offer::Full);
+
+        let sender = tx.clone();
+        let task = tokio::spawn(async move { sender.send_frame(frame_b.unwrap()).await });
+
+        assert_eq!(rx.recv().await.unwrap().to_string(), "a");
+        assert_eq!(task.await.unwrap().unwrap(), Offer::Queued);
+        assert_eq!(rx.recv().await.unwrap().to_string(), "b");
+    }
+}
@@ -0,0 +1,182 @@
+use crate::{
+    broadcast::Audience, event::Event, topic::Topic, websocket::WebSocket,
+    websocket_state::do_broadcast,
+};
+use axum::{
+    body::Bytes,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+/// Body of `POST {path}/broadcast`.
+#[derive(Debug, Deserialize)]
+pub(crate) struct PublishRequest {
+    topic: String,
+    event: String,
+    #[serde(default)]
+    payload: Value,
+    /// Socket ids that should not receive the broadcast, as with `broadcast_except`.
+    #[serde(default)]
+    exclude: Exclude,
+}
+
+/// One socket id to exclude, or a list of them.
+#[derive(Debug, Default, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum Exclude {
+    #[default]
+    None,
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Exclude {
+    fn ids(&self) -> Vec<&str> {
+        match self {
+            Exclude::None => vec![],
+            Exclude::One(id) => vec![id],
+            Exclude::Many(ids) => ids.iter().map(String::as_str).collect(),
+        }
+    }
+}
+
+/// Why an event may not be published: clients would take a reserved one for a
+/// protocol message, and a line break would let it forge SSE fields.
+fn invalid_event(event: &str) -> Option<&'static str> {
+    if event.contains(['\r', '\n']) {
+        Some("event must not contain line breaks")
+    } else if event.starts_with("phx_") || !matches!(Event::from(event), Event::Custom(_)) {
+        Some("event is reserved")
+    } else {
+        None
+    }
+}
+
+impl<T> WebSocket<T>
+where
+    T: Default + Send + Sync + 'static,
+{
+    /// `POST {path}/broadcast`: broadcast on behalf of services that cannot call
+    /// [`WebSocket::broadcast`], answering with the local [`BroadcastReport`](crate::BroadcastReport).
+    ///
+    /// The body is only parsed once the request is authorized, so that nobody
+    /// learns about the expected shape without credentials.
+    pub(crate) async fn publish_handler(
+        headers: HeaderMap,
+        Extension(websocket): Extension<Arc<WebSocket<T>>>,
+        body: Bytes,
+    ) -> Response {
+        let Some(authorize) = websocket.publish.as_ref() else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+
+        let res = authorize.call(headers).await;
+
+        if res.status().is_client_error() || res.status().is_server_error() {
+            return res;
+        }
+
+        let request = match serde_json::from_slice::<PublishRequest>(&body) {
+            Ok(request) => request,
+            Err(err) => return (StatusCode::UNPROCESSABLE_ENTITY, err.to_string()).into_response(),
+        };
+
+        if let Some(reason) = invalid_event(&request.event) {
+            return (StatusCode::UNPROCESSABLE_ENTITY, reason).into_response();
+        }
+
+        let topic = Topic::from(request.topic);
+        let exclude = request.exclude.ids();
+        let report = do_broadcast(
+            Audience::except(&exclude),
+            Some(&websocket.path),
+            Some(&topic),
+            &request.event,
+            Ok(request.payload),
+            None,
+        )
+        .await;
+
+        match report {
+            Ok(report) => Json(json!({
+                "targeted": report.targeted,
+                "delivered": report.delivered,
+                "dropped": report.dropped,
+                "failed": report.failed,
+            }))
+            .into_response(),
+            Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn publish_should_authorize_before_parsing_the_body() {
+        #[derive(Default)]
+        struct Endpoint;
+
+        let websocket = Arc::new(WebSocket::<Endpoint>::new("/publish").publish(
+            |headers: HeaderMap| async move {
+                match headers.contains_key("authorization") {
+                    true => StatusCode::OK,
+                    false => StatusCode::UNAUTHORIZED,
+                }
+            },
+        ));
+        let publish = |headers: HeaderMap, body: &'static str| {
+            WebSocket::<Endpoint>::publish_handler(
+                headers,
+                Extension(websocket.clone()),
+                Bytes::from(body),
+            )
+        };
+        let mut authorized = HeaderMap::new();
+        authorized.insert("authorization", "Bearer secret".parse().unwrap());
+
+        let res = publish(HeaderMap::new(), "not json").await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let res = publish(authorized.clone(), "not json").await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let body = r#"{"topic": "room:1", "event": "news"}"#;
+        let res = publish(authorized, body).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn publish_should_reject_reserved_and_multiline_events() {
+        #[derive(Default)]
+        struct Endpoint;
+
+        let websocket = Arc::new(
+            WebSocket::<Endpoint>::new("/publish_events")
+                .publish(|_headers: HeaderMap| async { StatusCode::OK }),
+        );
+
+        for event in [
+            "phx_reply",
+            "phx_custom",
+            "heartbeat",
+            "close",
+            "news\nid: 1",
+        ] {
+            let body = json!({"topic": "room:1", "event": event}).to_string();
+            let res = WebSocket::<Endpoint>::publish_handler(
+                HeaderMap::new(),
+                Extension(websocket.clone()),
+                Bytes::from(body),
+            )
+            .await;
+            assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY, "{event}");
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use crate::{message::Message, websocket_error::WebSocketError};
+use serde_json::{json, Value};
+use std::{fmt, sync::Arc};
+
+/// Wire format negotiated through the `vsn` connect parameter.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Serializer {
+    /// `[join_ref, ref, topic, event, payload]` arrays, phoenix.js `vsn=2.0.0`.
+    #[default]
+    V2,
+    /// Server-Sent Events blocks carrying `{"topic", "payload"}` as data, outbound only.
+    Sse,
+}
+
+impl Serializer {
+    /// Only phoenix.js `vsn=2.0.0` is spoken, whatever the client asks for.
+    pub(crate) fn from_vsn(_vsn: Option<&str>) -> Self {
+        Self::V2
+    }
+
+    /// The phoenix.js `vsn` this serializer speaks, `None` for SSE.
+    pub(crate) fn vsn(&self) -> Option<&'static str> {
+        match self {
+            Self::V2 => Some("2.0.0"),
+            Self::Sse => None,
+        }
+    }
+
+    pub(crate) fn encode(&self, message: &Message) -> Frame {
+        self.encode_with_id(message, None)
+    }
+
+    /// Encode a message, tagging it with a replay id where the wire format has room for one.
+    pub(crate) fn encode_with_id(&self, message: &Message, id: Option<u64>) -> Frame {
+        match self {
+            Self::V2 => Frame::from(message.to_string()),
+            Self::Sse => {
+                let payload: Value = message.payload.clone().into();
+                let data = json!({"topic": &*message.topic, "payload": payload});
+                let id = id.map(|id| format!("id: {id}\n")).unwrap_or_default();
+                // A line break in the event name would end the field and let it forge others.
+                let event = message.event.to_string().replace(['\r', '\n'], "");
+
+                Frame::from(format!("{id}event: {event}\ndata: {data}\n\n"))
+            }
+        }
+    }
+
+    pub(crate) fn decode(&self, text: &str) -> Result<Message, WebSocketError> {
+        match self {
+            Self::V2 => text.try_into(),
+            Self::Sse => Err(WebSocketError::InvalidMessage(
+                "server-sent events are outbound only".to_string(),
+            )),
+        }
+    }
+}
+
+/// An encoded text frame, cheap to clone so one encoding can be shared by every recipient.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Frame(Arc<str>);
+
+impl Frame {
+    pub(crate) fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Frame {
+    fn from(s: String) -> Self {
+        Self(s.into())
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Encodes a message at most once per serializer, however many recipients share it.
+pub(crate) struct FrameCache<'a> {
+    message: &'a Message,
+    id: Option<u64>,
+    v2: Option<Frame>,
+    sse: Option<Frame>,
+}
+
+impl<'a> FrameCache<'a> {
+    pub(crate) fn new(message: &'a Message) -> Self {
+        Self {
+            message,
+            id: None,
+            v2: None,
+            sse: None,
+        }
+    }
+
+    /// Tag the frames with a replay id, see [`Serializer::encode_with_id`].
+    pub(crate) fn with_id(mut self, id: Option<u64>) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub(crate) fn get(&mut self, serializer: Serializer) -> Frame {
+        let (message, id) = (self.message, self.id);
+        let slot = match serializer {
+            Serializer::V2 => &mut self.v2,
+            Serializer::Sse => &mut self.sse,
+        };
+
+        slot.get_or_insert_with(|| serializer.encode_with_id(message, id))
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializer_should_round_trip() {
+        let v2 = r#"["1","2","room:1","new_msg",{"body":"hi"}]"#;
+        let message = Serializer::V2.decode(v2).unwrap();
+        assert_eq!(Serializer::V2.encode(&message).as_str(), v2);
+    }
+
+    #[test]
+    fn frame_cache_should_encode_once_per_serializer() {
+        let message = Serializer::V2
+            .decode(r#"[null,null,"room:1","new_msg",{}]"#)
+            .unwrap();
+        let mut cache = FrameCache::new(&message);
+
+        let a = cache.get(Serializer::V2);
+        let b = cache.get(Serializer::V2);
+        assert!(Arc::ptr_eq(&a.0, &b.0));
+        assert_ne!(cache.get(Serializer::Sse), a);
+    }
+
+    #[test]
+    fn sse_should_encode_event_blocks() {
+        let message = Serializer::V2
+            .decode(r#"[null,null,"room:1","new_msg",{"body":"hi"}]"#)
+            .unwrap();
+        let frame = FrameCache::new(&message)
+            .with_id(Some(7))
+            .get(Serializer::Sse);
+
+        assert_eq!(
+            frame.as_str(),
+            "id: 7\nevent: new_msg\ndata: {\"payload\":{\"body\":\"hi\"},\"topic\":\"room:1\"}\n\n"
+        );
+    }
+
+    #[test]
+    fn sse_should_strip_line_breaks_from_event_names() {
+        let message = Serializer::V2
+            .decode(r#"[null,null,"room:1","new_msg\r\nid: 99\n\ndata: forged",{}]"#)
+            .unwrap();
+        let frame = Serializer::Sse.encode(&message);
+
+        assert_eq!(
+            frame.as_str(),
+            "event: new_msgid: 99data: forged\ndata: {\"payload\":{},\"topic\":\"room:1\"}\n\n"
+        );
+    }
+}
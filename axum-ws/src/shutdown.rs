@@ -0,0 +1,210 @@
+use crate::{
+    broadcast::Audience,
+    websocket::WebSocket,
+    websocket_state::{do_broadcast, WEBSOCKET_STATE},
+};
+use serde_json::Value;
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+use tokio::sync::watch;
+
+/// Settings of [`WebSocket::shutdown`].
+#[derive(Debug, Clone)]
+pub struct GracefulShutdown {
+    timeout: Duration,
+    event: Option<(String, Value)>,
+}
+
+impl Default for GracefulShutdown {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+            event: None,
+        }
+    }
+}
+
+impl GracefulShutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How long connections get to flush their queues, defaults to 10 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Broadcast `event` on every topic with subscribers before closing connections.
+    pub fn broadcast(mut self, event: impl Into<String>, payload: Value) -> Self {
+        self.event = Some((event.into(), payload));
+        self
+    }
+}
+
+/// Shutdown state of an endpoint, shared with its connections.
+pub(crate) struct Shutdown {
+    config: GracefulShutdown,
+    draining: AtomicBool,
+    signal: watch::Sender<bool>,
+}
+
+impl Shutdown {
+    pub(crate) fn new(config: GracefulShutdown) -> Self {
+        Self {
+            config,
+            draining: AtomicBool::new(false),
+            signal: watch::Sender::new(false),
+        }
+    }
+
+    /// Whether new clients are being turned away.
+    pub(crate) fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn timeout(&self) -> Duration {
+        self.config.timeout
+    }
+
+    /// Follow the signal telling connections to close; shutdown waits for every
+    /// subscription to be dropped.
+    pub(crate) fn subscribe(&self) -> watch::Receiver<bool> {
+        self.signal.subscribe()
+    }
+}
+
+/// Resolve once the endpoint starts shutting down, never without a signal to follow.
+pub(crate) async fn signalled(signal: &mut Option<watch::Receiver<bool>>) {
+    if let Some(signal) = signal {
+        if signal.wait_for(|closing| *closing).await.is_ok() {
+            return;
+        }
+    }
+
+    std::future::pending().await
+}
+
+impl<T> WebSocket<T>
+where
+    T: Default + Send + Sync + 'static,
+{
+    /// Drain the endpoint before the server stops.
+    ///
+    /// New clients are refused with `503 Service Unavailable`, the configured
+    /// event is broadcast, and every connection runs its channels' `terminate`
+    /// callbacks, flushes its queue and is closed with `1001 Going Away`, so
+    /// clients reconnect to another node right away. Resolves once every
+    /// connection is closed or the timeout has passed.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use axum_ws::WebSocket;
+    /// # use std::future::Future;
+    /// # async fn run(
+    /// #     listener: tokio::net::TcpListener,
+    /// #     app: axum::Router,
+    /// #     deploy: impl Future<Output = ()> + Send + 'static,
+    /// # ) {
+    /// axum::serve(listener, app)
+    ///     .with_graceful_shutdown(async {
+    ///         deploy.await;
+    ///         WebSocket::<()>::shutdown().await;
+    ///     })
+    ///     .await
+    ///     .unwrap();
+    /// # }
+    /// ```
+    pub async fn shutdown() {
+        let Some(path) = WEBSOCKET_STATE.get_path::<T>() else {
+            return;
+        };
+
+        let Some(shutdown) = WEBSOCKET_STATE.get_shutdown(&path) else {
+            return;
+        };
+
+        shutdown.draining.store(true, Ordering::Release);
+
+        if let Some((event, payload)) = shutdown.config.event.as_ref() {
+            for topic in WEBSOCKET_STATE.get_topics(&path) {
+                let data = Ok(payload.clone());
+                let _ = do_broadcast(
+                    Audience::default(),
+                    Some(&path),
+                    Some(&topic),
+                    event,
+                    data,
+                    None,
+                )
+                .await;
+            }
+        }
+
+        shutdown.signal.send_replace(true);
+
+        let _ = tokio::time::timeout(shutdown.timeout(), shutdown.signal.closed()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_util::fixture::join,
+        transport::{MemoryTransport, TransportMessage},
+        Channel, Socket, Topic,
+    };
+    use axum::{extract::ws::close_code, http::StatusCode};
+    use futures::{SinkExt, StreamExt};
+    use serde_json::json;
+    use std::sync::{atomic::AtomicUsize, Arc};
+
+    static TERMINATED: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Default)]
+    struct Endpoint;
+
+    #[tokio::test]
+    async fn shutdown_should_drain_and_close_connections() {
+        async fn terminate(_topic: Topic, _socket: Socket) {
+            TERMINATED.fetch_add(1, Ordering::SeqCst);
+        }
+
+        let websocket = Arc::new(
+            WebSocket::<Endpoint>::new("/shutdown")
+                .graceful_shutdown(GracefulShutdown::new().broadcast("bye", json!({})))
+                .channel("room:*", Channel::new().join(join).terminate(terminate)),
+        );
+
+        let accepted = websocket.clone().accept(json!({})).await.unwrap();
+        let (server, mut client) = MemoryTransport::pair();
+        tokio::spawn(accepted.serve(server));
+
+        let join = json!(["1", "1", "room:1", "phx_join", {}]).to_string();
+        client.send(TransportMessage::Text(join)).await.unwrap();
+        assert!(matches!(
+            client.next().await,
+            Some(TransportMessage::Text(_))
+        ));
+
+        WebSocket::<Endpoint>::shutdown().await;
+
+        let Some(TransportMessage::Text(bye)) = client.next().await else {
+            panic!("expected the shutdown broadcast");
+        };
+        assert_eq!(serde_json::from_str::<Value>(&bye).unwrap()[3], "bye");
+
+        let Some(TransportMessage::Close(Some(frame))) = client.next().await else {
+            panic!("expected a close frame");
+        };
+        assert_eq!(frame.code, close_code::AWAY);
+        assert_eq!(TERMINATED.load(Ordering::SeqCst), 1);
+
+        let res = websocket.accept(json!({})).await.err().unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
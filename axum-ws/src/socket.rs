@@ -1,33 +1,76 @@
 use crate::{
     assigns::Assigns,
+    broadcast::{Audience, BroadcastReport},
     handler::IntoResponse,
     message::Message,
     topic::Topic,
+    topic_params::{Params, TopicParams},
     websocket_state::{do_broadcast, WEBSOCKET_STATE},
 };
 use anyhow::Result;
+use serde::de::DeserializeOwned;
 use serde_json::Value;
+use std::sync::{Arc, Mutex};
 
 #[derive(Debug, Default, Clone)]
 
 pub struct Socket {
     pub(crate) id: String,
+    pub(crate) connection_id: String,
     pub(crate) joined: bool,
     pub(crate) path: String,
     pub(crate) topic: Option<Topic>,
     pub(crate) message: Option<Message>,
+    pub(crate) params: Params,
+    /// Channel assigns, private to the topic. Every channel gets a copy of the
+    /// connection socket's `assigns` when it joins, so what `connect` left here is
+    /// seen by all channels, but writes made afterwards, on either side, are not.
+    /// Use [`Socket::with_connection_assigns`] for data every channel should see.
     pub assigns: Assigns,
+    /// Connection assigns, the one map every copy of the socket points to.
+    connection_assigns: Arc<Mutex<Assigns>>,
 }
 
 impl Socket {
     pub fn new(id: impl Into<String>, path: impl Into<String>) -> Self {
+        let id = id.into();
+
         Self {
-            id: id.into(),
+            connection_id: id.clone(),
+            id,
             path: path.into(),
             ..Default::default()
         }
     }
 
+    /// The socket id, shared by every connection the `id` callback maps to the
+    /// same value, e.g. one per device of a user.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Identifies this connection alone, unlike [`Socket::id`].
+    pub fn connection_id(&self) -> &str {
+        &self.connection_id
+    }
+
+    /// Run `f` on the connection assigns, shared by reference between the
+    /// connection and all of its channels, so a write is seen everywhere right
+    /// away. Unlike [`Socket::assigns`], they are never copied.
+    ///
+    /// The map is locked while `f` runs, so `f` must not call back into this method.
+    pub fn with_connection_assigns<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut Assigns) -> R,
+    {
+        let mut assigns = self
+            .connection_assigns
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        f(&mut assigns)
+    }
+
     pub(crate) fn set_id(&mut self, id: impl Into<String>) {
         self.id = id.into();
     }
@@ -36,6 +79,10 @@ impl Socket {
         self.topic = Some(topic);
     }
 
+    pub(crate) fn set_params(&mut self, params: Params) {
+        self.params = params;
+    }
+
     pub(crate) fn set_joined(&mut self, joined: bool) {
         self.joined = joined;
     }
@@ -44,13 +91,70 @@ impl Socket {
         self.message = Some(message);
     }
 
+    /// A single segment captured by `{name}` in the channel's topic pattern.
+    pub fn topic_param(&self, name: &str) -> Option<&str> {
+        self.params.get(name)
+    }
+
+    /// The segments captured by the channel's topic pattern, deserialized into `T`.
+    pub fn topic_params<T>(&self) -> Result<TopicParams<T>>
+    where
+        T: DeserializeOwned,
+    {
+        Ok(TopicParams(self.params.deserialize()?))
+    }
+
+    /// Have the channel receive the broadcasts on another topic without the client
+    /// joining it, e.g. `"user:7:notifications"` from the `join` of `"room:42"`.
+    ///
+    /// They are pushed on the channel's own topic, through its `intercept` callback
+    /// when it has one, until [`Socket::unsubscribe`] or the channel is left.
+    pub fn subscribe(&self, topic: &str) -> Result<()> {
+        let owner = self.channel_topic()?;
+
+        WEBSOCKET_STATE.insert_relay(
+            (self.path.clone(), topic.into()),
+            self.connection_id.clone().into(),
+            owner.clone(),
+        );
+
+        Ok(())
+    }
+
+    /// Stop relaying a topic added with [`Socket::subscribe`], returning whether it was.
+    pub fn unsubscribe(&self, topic: &str) -> Result<bool> {
+        let owner = self.channel_topic()?;
+
+        Ok(WEBSOCKET_STATE.remove_relay(
+            &(self.path.clone(), topic.into()),
+            &self.connection_id.clone().into(),
+            owner,
+        ))
+    }
+
+    /// The channel's topic, or the one being joined while in `join`.
+    fn channel_topic(&self) -> Result<&Topic> {
+        self.topic
+            .as_ref()
+            .or(self.message.as_ref().map(|m| &m.topic))
+            .filter(|topic| !topic.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("socket is not in a channel"))
+    }
+
+    /// Identifies the channel instance the socket belongs to, see [`Socket::subscribe`].
+    pub(crate) fn channel_key(&self) -> Option<(String, Topic)> {
+        let topic = self.channel_topic().ok()?;
+
+        Some((self.connection_id.clone(), topic.clone()))
+    }
+
     pub(crate) async fn push_message(&self, mut message: Message) -> Result<()> {
-        if let Some(tx) = WEBSOCKET_STATE.get_sender(&self.id) {
+        if let Some(tx) = WEBSOCKET_STATE.get_sender(&self.connection_id) {
             if let Some(m) = self.message.as_ref() {
                 message.merge(m);
             }
 
-            tx.send(message).await?;
+            tx.send(&message).await?;
         }
 
         Ok(())
@@ -69,23 +173,15 @@ impl Socket {
             message.merge(m);
         }
 
-        if let Some(tx) = WEBSOCKET_STATE.get_sender(&self.id) {
-            tx.send(message).await?;
+        if let Some(tx) = WEBSOCKET_STATE.get_sender(&self.connection_id) {
+            tx.send(&message).await?;
         }
 
         Ok(())
     }
 
-    pub async fn broadcast(&self, event: &str, data: Result<Value>) -> Result<()> {
-        do_broadcast(
-            None,
-            Some(&self.path),
-            self.topic.as_ref(),
-            event,
-            data,
-            self.message.as_ref(),
-        )
-        .await
+    pub async fn broadcast(&self, event: &str, data: Result<Value>) -> Result<BroadcastReport> {
+        self.broadcast_to(Audience::default(), event, data).await
     }
 
     pub async fn broadcast_from(
@@ -93,9 +189,46 @@ impl Socket {
         user_id: &str,
         event: &str,
         data: Result<Value>,
-    ) -> Result<()> {
+    ) -> Result<BroadcastReport> {
+        self.broadcast_except(&[user_id], event, data).await
+    }
+
+    /// Broadcast on the channel's topic except to the connections of the given socket ids.
+    pub async fn broadcast_except(
+        &self,
+        exclude: &[&str],
+        event: &str,
+        data: Result<Value>,
+    ) -> Result<BroadcastReport> {
+        self.broadcast_to(Audience::except(exclude), event, data)
+            .await
+    }
+
+    /// Broadcast on the channel's topic to the subscribers whose channel socket
+    /// satisfies `filter`, see [`crate::WebSocket::broadcast_filter`].
+    ///
+    /// This socket is passed to `filter` as is, so it can be called while locked.
+    pub async fn broadcast_filter<F>(
+        &self,
+        event: &str,
+        data: Result<Value>,
+        filter: F,
+    ) -> Result<BroadcastReport>
+    where
+        F: Fn(&Socket) -> bool + Send + Sync,
+    {
+        self.broadcast_to(Audience::filter(&filter), event, data)
+            .await
+    }
+
+    async fn broadcast_to(
+        &self,
+        audience: Audience<'_>,
+        event: &str,
+        data: Result<Value>,
+    ) -> Result<BroadcastReport> {
         do_broadcast(
-            Some(user_id),
+            audience.sent_by(self),
             Some(&self.path),
             self.topic.as_ref(),
             event,
@@ -105,3 +238,82 @@ impl Socket {
         .await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_util::ChannelTest, Channel, Payload, Socket, Topic, WebSocket};
+    use anyhow::Result;
+    use serde_json::{json, Value};
+
+    #[derive(Default)]
+    struct Endpoint;
+
+    #[tokio::test]
+    async fn connection_assigns_should_be_shared_and_channel_assigns_private() {
+        async fn connect(_params: Value, socket: Socket) {
+            let socket = socket.lock().await;
+            socket.with_connection_assigns(|assigns| assigns.insert("user", "alice".to_string()));
+        }
+
+        async fn join(topic: Topic, _payload: Payload, socket: Socket) -> Result<Value> {
+            let mut socket = socket.lock().await;
+            socket.assigns.insert("room", topic.to_string());
+            socket.with_connection_assigns(|assigns| {
+                assigns.insert("last_room", topic.to_string());
+            });
+
+            Ok(json!({}))
+        }
+
+        async fn whoami(_payload: Payload, socket: Socket) -> Result<Value> {
+            let socket = socket.lock().await;
+            let (user, last_room) = socket.with_connection_assigns(|assigns| {
+                (
+                    assigns.get::<String>("user").cloned(),
+                    assigns.get::<String>("last_room").cloned(),
+                )
+            });
+
+            Ok(json!({
+                "user": user,
+                "last_room": last_room,
+                "room": socket.assigns.get::<String>("room"),
+            }))
+        }
+
+        let websocket = WebSocket::<Endpoint>::new("/assigns")
+            .connect(connect)
+            .channel(
+                "room:*",
+                Channel::new().join(join).handler("whoami", whoami),
+            );
+        let test = ChannelTest::new(websocket);
+        let socket = test.connect(json!({})).await.unwrap();
+        let (_, lobby) = socket
+            .subscribe_and_join("room:lobby", json!({}))
+            .await
+            .unwrap();
+        let (_, game) = socket
+            .subscribe_and_join("room:game", json!({}))
+            .await
+            .unwrap();
+
+        let reply = lobby.push("whoami", json!({})).await;
+        assert_eq!(
+            lobby.assert_reply(&reply, "ok"),
+            json!({"user": "alice", "last_room": "room:game", "room": "room:lobby"})
+        );
+
+        socket
+            .socket()
+            .lock()
+            .await
+            .with_connection_assigns(|assigns| assigns.insert("user", "bob".to_string()));
+
+        let reply = game.push("whoami", json!({})).await;
+        assert_eq!(
+            game.assert_reply(&reply, "ok"),
+            json!({"user": "bob", "last_room": "room:game", "room": "room:game"})
+        );
+    }
+}
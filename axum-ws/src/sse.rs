@@ -0,0 +1,457 @@
+use crate::{
+    channel::Channel,
+    connection::{Connection, Terminating},
+    handler::IntoResponse,
+    outbound,
+    payload::Payload,
+    serializer::{Frame, Serializer},
+    shutdown,
+    topic::Topic,
+    topic_router::Match,
+    user_id::UserId,
+    websocket::WebSocket,
+    websocket_state::WEBSOCKET_STATE,
+    Socket,
+};
+use axum::{
+    body::Body,
+    extract::Query,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse as _, Response},
+    Extension, Json,
+};
+use serde_json::{json, Value};
+use std::{
+    collections::VecDeque,
+    convert::Infallible,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tracing::{Instrument, Span};
+
+/// Settings of the read-only Server-Sent Events transport, mounted at `{path}/sse`.
+///
+/// Clients name the topics to follow in the query string, e.g.
+/// `/socket/sse?topics=room:1,room:2&token=...`. The endpoint's `connect` callback
+/// authenticates the request and every channel's `join` authorizes its topic, after
+/// which broadcasts arrive as `event: <event>` with `{"topic", "payload"}` as data.
+///
+/// While streams are open, broadcasts are numbered per endpoint and the most recent
+/// ones are kept, so a client reconnecting with `Last-Event-ID` (or `last_event_id`
+/// in the query string) is first sent whatever it missed.
+#[derive(Debug, Clone)]
+pub struct ServerSentEvents {
+    history_size: usize,
+    keep_alive: Duration,
+}
+
+impl Default for ServerSentEvents {
+    fn default() -> Self {
+        Self {
+            history_size: 1024,
+            keep_alive: Duration::from_secs(15),
+        }
+    }
+}
+
+impl ServerSentEvents {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of recent broadcasts kept for `Last-Event-ID` replay, defaults to 1024.
+    pub fn history_size(mut self, history_size: usize) -> Self {
+        self.history_size = history_size;
+        self
+    }
+
+    /// Interval of the comments sent to keep idle streams open, defaults to 15 seconds.
+    pub fn keep_alive(mut self, keep_alive: Duration) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    pub(crate) fn history(&self) -> History {
+        History {
+            capacity: self.history_size,
+            next_id: 1,
+            streams: 0,
+            events: VecDeque::new(),
+        }
+    }
+}
+
+/// The most recent broadcasts of an endpoint, encoded for SSE and tagged with their id.
+pub(crate) struct History {
+    capacity: usize,
+    next_id: u64,
+    /// Open streams, broadcasts are neither numbered nor kept without any.
+    streams: usize,
+    events: VecDeque<(u64, Topic, Frame)>,
+}
+
+impl History {
+    /// Id of the next broadcast, `None` while no stream is open.
+    pub(crate) fn next_id(&mut self) -> Option<u64> {
+        if self.streams == 0 {
+            return None;
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        Some(id)
+    }
+
+    /// Keep a broadcast, in id order since concurrent broadcasts may finish encoding
+    /// in any order.
+    pub(crate) fn push(&mut self, id: u64, topic: Topic, frame: Frame) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+        }
+
+        let at = self
+            .events
+            .iter()
+            .rposition(|(other, _, _)| *other < id)
+            .map_or(0, |at| at + 1);
+        self.events.insert(at, (id, topic, frame));
+    }
+
+    fn since<'a>(
+        &'a self,
+        last_id: u64,
+        topics: &'a [Topic],
+    ) -> impl Iterator<Item = (u64, &'a Frame)> + 'a {
+        self.events
+            .iter()
+            .filter(move |(id, topic, _)| *id > last_id && topics.contains(topic))
+            .map(|(id, _, frame)| (*id, frame))
+    }
+}
+
+/// The id a frame was tagged with, see [`Serializer::encode_with_id`].
+fn event_id(frame: &Frame) -> Option<u64> {
+    let (id, _) = frame.as_str().strip_prefix("id: ")?.split_once('\n')?;
+    id.parse().ok()
+}
+
+/// Leave the channels joined so far once joining `failed` did not succeed, dropping
+/// whatever their joins relayed, as on a `Connection`.
+fn leave(
+    user_id: &UserId,
+    failed: &Topic,
+    channels: Vec<(Arc<Channel>, Topic, Socket, Span)>,
+) -> Terminating {
+    WEBSOCKET_STATE.remove_relays(user_id, failed);
+
+    for (_, topic, _, _) in channels.iter() {
+        WEBSOCKET_STATE.remove_relays(user_id, topic);
+    }
+
+    Terminating(channels)
+}
+
+/// Removes the stream's registrations once the client goes away and the body is dropped.
+struct Registration {
+    user_id: UserId,
+    history: Option<Arc<Mutex<History>>>,
+    channels: Vec<(Arc<Channel>, Topic, Socket, Span)>,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        WEBSOCKET_STATE.clearn_user(&self.user_id);
+
+        if let Some(history) = self.history.as_ref() {
+            history.lock().unwrap().streams -= 1;
+        }
+
+        let terminating = Terminating(std::mem::take(&mut self.channels));
+
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(terminating.run());
+        }
+
+        tracing::info!(
+            id = self.user_id.as_str(),
+            reason = "client closed",
+            "socket disconnected"
+        );
+    }
+}
+
+impl<T> WebSocket<T>
+where
+    T: Default + Send + Sync + 'static,
+{
+    /// `GET {path}/sse`: stream the broadcasts of the topics listed in the query string.
+    pub(crate) async fn sse_stream(
+        Query(params): Query<Value>,
+        headers: HeaderMap,
+        Extension(websocket): Extension<Arc<WebSocket<T>>>,
+    ) -> Response {
+        let Some(config) = websocket.sse.clone() else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+
+        let topics = params
+            .get("topics")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .split(',')
+            .filter(|topic| !topic.is_empty())
+            .map(Topic::from)
+            .collect::<Vec<_>>();
+
+        if topics.is_empty() {
+            return (StatusCode::BAD_REQUEST, "no topics requested").into_response();
+        }
+
+        let last_event_id = headers
+            .get("last-event-id")
+            .and_then(|value| value.to_str().ok())
+            .or_else(|| params.get("last_event_id").and_then(Value::as_str))
+            .and_then(|id| id.parse::<u64>().ok());
+
+        let socket = match Connection::authenticate(&websocket, params).await {
+            Ok(socket) => socket,
+            Err(res) => return res,
+        };
+
+        let (socket_id, user_id) = {
+            let socket = socket.lock().await;
+            (
+                socket.id.clone(),
+                UserId::from(socket.connection_id.clone()),
+            )
+        };
+        let mut channels = vec![];
+
+        for topic in topics.iter() {
+            let denied = |response: Value| {
+                let body = json!({"topic": &**topic, "response": response});
+                (StatusCode::FORBIDDEN, Json(body)).into_response()
+            };
+
+            let Some(Match {
+                value: channel,
+                params,
+                pattern,
+            }) = websocket.get_channel(topic)
+            else {
+                leave(&user_id, topic, channels).run().await;
+                return denied("unmatched topic".into());
+            };
+
+            let Some(join) = channel.join.as_ref() else {
+                leave(&user_id, topic, channels).run().await;
+                return denied("unmatched topic".into());
+            };
+
+            // Every topic gets a channel socket of its own, as on a `Connection`.
+            let channel_socket = {
+                let mut channel_socket = socket.lock().await.clone();
+                channel_socket.set_params(params);
+                channel_socket.set_topic(topic.clone());
+                Arc::new(tokio::sync::Mutex::new(channel_socket))
+            };
+            let span = tracing::info_span!("channel", topic = %&**topic, pattern);
+
+            if let Err(err) = join
+                .call(topic.clone(), Payload::default(), channel_socket.clone())
+                .instrument(span.clone())
+                .await
+            {
+                let response: Value = Err::<Value, _>(err).into_response().into();
+                leave(&user_id, topic, channels).run().await;
+                return denied(response["response"].clone());
+            }
+
+            channel_socket.lock().await.set_joined(true);
+            channels.push((channel.clone(), topic.clone(), channel_socket, span));
+        }
+
+        let mut snapshots = vec![];
+        for (_, _, channel_socket, _) in channels.iter() {
+            snapshots.push(channel_socket.lock().await.clone());
+        }
+        let (tx, rx) = outbound::channel(
+            Serializer::Sse,
+            websocket.buffer_size,
+            websocket.overflow_policy,
+            websocket.block_timeout,
+        );
+
+        // Broadcasts are recorded before they are fanned out, so registering under the
+        // history lock splits them cleanly: those before are replayed from the history,
+        // those after arrive through the queue. A broadcast recorded but not yet fanned
+        // out may come both ways, the stream skips the queued copy.
+        let history = WEBSOCKET_STATE.get_history(&websocket.path);
+        let mut replayed = 0;
+        {
+            let mut guard = history.as_ref().map(|history| history.lock().unwrap());
+
+            if let Some(history) = guard.as_mut() {
+                history.streams += 1;
+            }
+
+            WEBSOCKET_STATE.insert_connection(
+                user_id.clone(),
+                socket_id,
+                &websocket.path,
+                Serializer::Sse,
+            );
+            WEBSOCKET_STATE.insert_sender(user_id.clone(), tx.clone());
+
+            for ((channel, topic, channel_socket, _), snapshot) in channels.iter().zip(snapshots) {
+                WEBSOCKET_STATE.insert_subscription(
+                    user_id.clone(),
+                    topic.clone(),
+                    channel_socket.clone(),
+                    snapshot,
+                    Some(channel.clone()),
+                );
+                WEBSOCKET_STATE
+                    .insert_user((websocket.path.clone(), topic.clone()), user_id.clone());
+            }
+
+            if let (Some(history), Some(last_event_id)) = (guard.as_ref(), last_event_id) {
+                for (id, frame) in history.since(last_event_id, &topics) {
+                    tx.offer(frame.clone());
+                    replayed = id;
+                }
+            }
+        }
+
+        let registration = Registration {
+            user_id,
+            history,
+            channels,
+        };
+        let signal = WEBSOCKET_STATE
+            .get_shutdown(&websocket.path)
+            .map(|shutdown| shutdown.subscribe());
+        let keep_alive = config.keep_alive;
+        let stream = futures::stream::unfold(
+            (rx, registration, signal),
+            move |(mut rx, registration, mut signal)| async move {
+                let chunk = loop {
+                    tokio::select! {
+                        frame = tokio::time::timeout(keep_alive, rx.recv()) => match frame {
+                            Ok(Some(frame))
+                                if event_id(&frame).is_some_and(|id| id <= replayed) =>
+                            {
+                                continue
+                            }
+                            Ok(Some(frame)) => break frame.as_str().to_owned(),
+                            Ok(None) => return None,
+                            Err(_) => break ": keep-alive\n\n".to_string(),
+                        },
+                        _ = shutdown::signalled(&mut signal) => return None,
+                    }
+                };
+
+                Some((Ok::<_, Infallible>(chunk), (rx, registration, signal)))
+            },
+        );
+
+        let mut response = Body::from_stream(stream).into_response();
+        let headers = response.headers_mut();
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("text/event-stream"),
+        );
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        headers.insert("x-accel-buffering", HeaderValue::from_static("no"));
+
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_should_replay_missed_events_of_subscribed_topics() {
+        let mut history = ServerSentEvents::new().history_size(2).history();
+        let topics = [Topic::from("room:1")];
+
+        assert_eq!(history.next_id(), None);
+        history.streams += 1;
+
+        for topic in ["room:1", "room:2", "room:1", "room:1"] {
+            let id = history.next_id().unwrap();
+            history.push(id, topic.into(), Frame::from(format!("{id}")));
+        }
+
+        let replayed = history
+            .since(0, &topics)
+            .map(|(_, frame)| frame.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(replayed, vec!["3", "4"]);
+
+        assert_eq!(history.since(3, &topics).count(), 1);
+    }
+    #[test]
+    fn history_should_keep_events_in_id_order() {
+        let mut history = ServerSentEvents::new().history();
+        history.streams += 1;
+
+        let (first, second) = (history.next_id().unwrap(), history.next_id().unwrap());
+        history.push(second, "room:1".into(), Frame::from("id: 2\n".to_string()));
+        history.push(first, "room:1".into(), Frame::from("id: 1\n".to_string()));
+
+        let ids = history
+            .since(0, &[Topic::from("room:1")])
+            .map(|(id, frame)| (id, event_id(frame)))
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![(1, Some(1)), (2, Some(2))]);
+    }
+    #[tokio::test]
+    async fn sse_stream_should_give_every_topic_its_own_channel_socket() {
+        static TERMINATED: Mutex<Vec<String>> = Mutex::new(vec![]);
+
+        #[derive(Default)]
+        struct Endpoint;
+
+        async fn join(_topic: Topic, _payload: Payload, socket: Socket) -> anyhow::Result<Value> {
+            socket.lock().await.subscribe("sse_lobby")?;
+            Ok(json!({}))
+        }
+
+        async fn terminate(_topic: Topic, socket: Socket) {
+            let id = socket.lock().await.topic_param("id").map(str::to_string);
+            TERMINATED.lock().unwrap().extend(id);
+        }
+
+        let websocket = Arc::new(
+            WebSocket::<Endpoint>::new("/sse_channels")
+                .sse(ServerSentEvents::new())
+                .channel(
+                    "sse_room:{id}",
+                    Channel::new().join(join).terminate(terminate),
+                ),
+        );
+
+        let params = json!({"topics": "sse_room:1,sse_room:2"});
+        let response = WebSocket::<Endpoint>::sse_stream(
+            Query(params),
+            HeaderMap::new(),
+            Extension(websocket.clone()),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        drop(response);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let mut terminated = TERMINATED.lock().unwrap().clone();
+        terminated.sort();
+        assert_eq!(terminated, vec!["1", "2"]);
+    }
+}
@@ -0,0 +1,223 @@
+use crate::{
+    channel::Channel,
+    channel_handler::Instances,
+    handler::{IntoResponse, Response},
+    payload::Payload,
+    topic::Topic,
+    Socket,
+};
+use anyhow::Result;
+use futures::Future;
+use serde_json::Value;
+
+/// A [`Channel`] with a typed state per joined topic, created by `join` and
+/// handed to every handler as `&mut S`, see [`Channel::with_state`].
+pub struct StatefulChannel<S> {
+    channel: Channel,
+    states: Instances<S>,
+}
+
+/// An async fn taking the channel state by mutable reference, such as
+/// `async fn handler(payload: Payload, state: &mut S, socket: Socket) -> Res`.
+pub trait StateHandler<'a, A, S: 'a, Res>: Send + Sync + 'static {
+    type Future: Future<Output = Res> + Send + 'a;
+
+    fn call(&self, arg: A, state: &'a mut S, socket: Socket) -> Self::Future;
+}
+
+impl<'a, A, S, Res, F, Fut> StateHandler<'a, A, S, Res> for F
+where
+    S: 'a,
+    F: Fn(A, &'a mut S, Socket) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Res> + Send + 'a,
+{
+    type Future = Fut;
+
+    fn call(&self, arg: A, state: &'a mut S, socket: Socket) -> Self::Future {
+        self(arg, state, socket)
+    }
+}
+
+impl Channel {
+    /// A channel whose `join` returns its reply along with the state of the
+    /// joined topic, which lives until the channel terminates.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use axum_ws::{Channel, Payload, Socket, Topic, WebSocket};
+    /// use serde_json::{json, Value};
+    ///
+    /// struct Room {
+    ///     user_id: i64,
+    ///     messages: usize,
+    /// }
+    ///
+    /// async fn join(_topic: Topic, payload: Payload, _socket: Socket) -> anyhow::Result<(Value, Room)> {
+    ///     let user_id = Value::from(payload)["user_id"].as_i64().unwrap_or_default();
+    ///     Ok((json!({}), Room { user_id, messages: 0 }))
+    /// }
+    ///
+    /// async fn new_msg(_payload: Payload, room: &mut Room, _socket: Socket) -> anyhow::Result<Value> {
+    ///     room.messages += 1;
+    ///     Ok(json!({"user_id": room.user_id, "messages": room.messages}))
+    /// }
+    ///
+    /// let room = Channel::with_state(join).handler("new_msg", new_msg);
+    /// let websocket = WebSocket::<()>::new("/socket").channel("room:*", room);
+    /// ```
+    pub fn with_state<S, F, Fut, Res>(join: F) -> StatefulChannel<S>
+    where
+        S: Send + 'static,
+        F: Fn(Topic, Payload, Socket) -> Fut + Clone + Send + Sync + 'static,
+        Fut: Future<Output = Result<(Res, S)>> + Send + 'static,
+        Res: Into<Value>,
+    {
+        let states = Instances::<S>::default();
+
+        let channel = Channel::new()
+            .join({
+                let states = states.clone();
+                move |topic, payload, socket: Socket| {
+                    let join = join.clone();
+                    let states = states.clone();
+                    async move {
+                        let (reply, state) = join(topic, payload, socket.clone()).await?;
+                        let reply: Value = reply.into();
+                        states.insert(&socket, state).await;
+
+                        Ok(reply)
+                    }
+                }
+            })
+            .terminate({
+                let states = states.clone();
+                move |_topic, socket| {
+                    let states = states.clone();
+                    async move {
+                        states.remove(&socket).await;
+                    }
+                }
+            });
+
+        StatefulChannel { channel, states }
+    }
+}
+
+impl<S> StatefulChannel<S>
+where
+    S: Send + 'static,
+{
+    pub fn handler<F, Res>(mut self, event: impl Into<String>, handler: F) -> Self
+    where
+        F: for<'a> StateHandler<'a, Payload, S, Res> + Clone,
+        Res: IntoResponse,
+    {
+        let states = self.states.clone();
+
+        self.channel = self.channel.handler(event, move |payload, socket: Socket| {
+            let handler = handler.clone();
+            let states = states.clone();
+            async move {
+                let Some(state) = states.get(&socket).await else {
+                    return Response::NoReply;
+                };
+
+                let mut state = state.lock().await;
+                handler
+                    .call(payload, &mut state, socket)
+                    .await
+                    .into_response()
+            }
+        });
+        self
+    }
+
+    /// Called with the state before it is dropped, when the client leaves the
+    /// topic, the channel is kicked or the connection closes.
+    pub fn terminate<F>(mut self, terminate: F) -> Self
+    where
+        F: for<'a> StateHandler<'a, Topic, S, ()> + Clone,
+    {
+        let states = self.states.clone();
+
+        self.channel = self.channel.terminate(move |topic, socket: Socket| {
+            let terminate = terminate.clone();
+            let states = states.clone();
+            async move {
+                if let Some(state) = states.remove(&socket).await {
+                    let mut state = state.lock().await;
+                    terminate.call(topic, &mut state, socket).await;
+                }
+            }
+        });
+        self
+    }
+}
+
+impl<S> From<StatefulChannel<S>> for Channel {
+    fn from(channel: StatefulChannel<S>) -> Self {
+        channel.channel
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_util::fixture::{connect, Endpoint},
+        WebSocket,
+    };
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static TERMINATED_WITH: AtomicUsize = AtomicUsize::new(0);
+
+    struct Room {
+        messages: usize,
+    }
+
+    #[tokio::test]
+    async fn stateful_channel_should_hand_state_to_handlers() {
+        async fn join(_topic: Topic, _payload: Payload, _socket: Socket) -> Result<(Value, Room)> {
+            Ok((json!({}), Room { messages: 0 }))
+        }
+
+        async fn new_msg(_payload: Payload, room: &mut Room, _socket: Socket) -> Result<Value> {
+            room.messages += 1;
+            Ok(json!({"messages": room.messages}))
+        }
+
+        async fn terminate(_topic: Topic, room: &mut Room, _socket: Socket) {
+            TERMINATED_WITH.store(room.messages, Ordering::SeqCst);
+        }
+
+        let room = Channel::with_state(join)
+            .handler("new_msg", new_msg)
+            .terminate(terminate);
+        let websocket = WebSocket::<Endpoint>::new("/stateful").channel("room:*", room);
+        let socket = connect(websocket).await;
+        let (_, lobby) = socket
+            .subscribe_and_join("room:lobby", json!({}))
+            .await
+            .unwrap();
+        let (_, game) = socket
+            .subscribe_and_join("room:game", json!({}))
+            .await
+            .unwrap();
+
+        for expected in 1..=2 {
+            let reply = lobby.push("new_msg", json!({})).await;
+            assert_eq!(
+                lobby.assert_reply(&reply, "ok"),
+                json!({"messages": expected})
+            );
+        }
+
+        let reply = game.push("new_msg", json!({})).await;
+        assert_eq!(game.assert_reply(&reply, "ok"), json!({"messages": 1}));
+
+        lobby.leave().await;
+        assert_eq!(TERMINATED_WITH.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,483 @@
+//! In-memory harness for testing channels, in the spirit of Phoenix's `ChannelTest`.
+//!
+//! Frames go straight through the same engine as the transports, so every reply,
+//! push and broadcast caused by a call is queued by the time it returns, and the
+//! assertions check what is queued instead of waiting for it.
+//!
+//! Channel state is global per endpoint path, so tests running concurrently
+//! should use distinct paths or topics.
+//!
+//! # Example
+//!
+//! ```
+//! use axum_ws::{test_util::ChannelTest, Channel, Payload, Socket, Topic, WebSocket};
+//! use serde_json::{json, Value};
+//!
+//! async fn join(_topic: Topic, _payload: Payload, _socket: Socket) -> anyhow::Result<Value> {
+//!     Ok(json!({"welcome": true}))
+//! }
+//!
+//! async fn shout(payload: Payload, socket: Socket) {
+//!     let socket = socket.lock().await;
+//!     socket.broadcast("shout", Ok(payload.into())).await.unwrap();
+//! }
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! let websocket = WebSocket::<()>::new("/doc_socket")
+//!     .channel("room:*", Channel::new().join(join).handler("shout", shout));
+//!
+//! let socket = ChannelTest::new(websocket).connect(json!({})).await.unwrap();
+//! let (reply, mut room) = socket.subscribe_and_join("room:lobby", json!({})).await.unwrap();
+//! assert_eq!(reply, json!({"welcome": true}));
+//!
+//! room.push("shout", json!({"body": "hi"})).await;
+//! assert_eq!(room.assert_broadcast("shout"), json!({"body": "hi"}));
+//! assert_eq!(room.assert_push("shout"), json!({"body": "hi"}));
+//! # }
+//! ```
+
+use crate::{
+    connection::Connection,
+    event::Event,
+    message::Message,
+    outbound::{
+        self, OutboundReceiver, OverflowPolicy, DEFAULT_BLOCK_TIMEOUT, DEFAULT_BUFFER_SIZE,
+    },
+    serializer::Serializer,
+    topic::Topic,
+    user_id::UserId,
+    websocket::WebSocket,
+    websocket_state::WEBSOCKET_STATE,
+    Socket,
+};
+use serde_json::{json, Value};
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+use tokio::sync::Mutex;
+
+/// Drives the channels of an endpoint without a network.
+pub struct ChannelTest<T> {
+    websocket: Arc<WebSocket<T>>,
+}
+
+impl<T> ChannelTest<T>
+where
+    T: Default + Send + Sync + 'static,
+{
+    pub fn new(websocket: WebSocket<T>) -> Self {
+        Self {
+            websocket: Arc::new(websocket),
+        }
+    }
+
+    /// Connect a client with the given params, running the endpoint's `connect`
+    /// and `id` callbacks, whose rejection is returned as is.
+    pub async fn connect(&self, params: Value) -> Result<TestSocket<T>, axum::response::Response> {
+        let socket = Connection::authenticate(&self.websocket, params).await?;
+        let user_id = socket.lock().await.connection_id.clone().into();
+        let (connection, rx) =
+            Connection::open(self.websocket.clone(), socket.clone(), Serializer::V2).await;
+
+        Ok(TestSocket {
+            inner: Arc::new(Inner {
+                connection: Mutex::new(connection),
+                socket,
+                user_id,
+                path: self.websocket.path.clone(),
+                mailbox: std::sync::Mutex::new(Mailbox::new(rx)),
+                refs: AtomicU64::new(0),
+            }),
+        })
+    }
+}
+
+struct Inner<T> {
+    connection: Mutex<Connection<T>>,
+    socket: Socket,
+    user_id: UserId,
+    path: String,
+    mailbox: std::sync::Mutex<Mailbox>,
+    refs: AtomicU64,
+}
+
+/// Channels of a dropped socket are terminated in the background, as a
+/// transport does when its client goes away.
+impl<T> Drop for Inner<T> {
+    fn drop(&mut self) {
+        let terminating = self.connection.get_mut().leave_all(&self.user_id);
+        WEBSOCKET_STATE.clearn_user(&self.user_id);
+
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(terminating.run());
+        }
+    }
+}
+
+/// A connected test client.
+pub struct TestSocket<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Clone for TestSocket<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> TestSocket<T>
+where
+    T: Default + Send + Sync + 'static,
+{
+    /// The connection's socket, as left by the `connect` and `id` callbacks.
+    pub fn socket(&self) -> Socket {
+        self.inner.socket.clone()
+    }
+
+    /// Subscribe to the topic's broadcasts and join it, returning the `response`
+    /// of the join reply, or of its error.
+    pub async fn subscribe_and_join(
+        &self,
+        topic: impl Into<Topic>,
+        payload: Value,
+    ) -> Result<(Value, TestChannel<T>), Value> {
+        let topic = topic.into();
+        let channel = TestChannel::subscribe(self.clone(), topic);
+        let message_ref = channel.send(Event::Join, payload).await;
+
+        let reply = channel
+            .take(|m| m.event == Event::Reply && m.message_ref.as_ref() == Some(&message_ref))
+            .map(|m| Value::from(m.payload))
+            .unwrap_or_default();
+
+        match reply["status"].as_str() {
+            Some("ok") => Ok((reply["response"].clone(), channel)),
+            _ => Err(reply["response"].clone()),
+        }
+    }
+}
+
+/// A joined topic of a [`TestSocket`].
+pub struct TestChannel<T> {
+    socket: TestSocket<T>,
+    topic: Topic,
+    join_ref: String,
+    spy: UserId,
+    broadcasts: Mailbox,
+}
+
+impl<T> TestChannel<T>
+where
+    T: Default + Send + Sync + 'static,
+{
+    fn subscribe(socket: TestSocket<T>, topic: Topic) -> Self {
+        let spy: UserId = nanoid::nanoid!().into();
+        let (tx, rx) = outbound::channel(
+            Serializer::V2,
+            DEFAULT_BUFFER_SIZE,
+            OverflowPolicy::DropOldest,
+            DEFAULT_BLOCK_TIMEOUT,
+        );
+
+        WEBSOCKET_STATE.insert_spy(spy.clone());
+        WEBSOCKET_STATE.insert_sender(spy.clone(), tx);
+        WEBSOCKET_STATE.insert_user((socket.inner.path.clone(), topic.clone()), spy.clone());
+
+        Self {
+            join_ref: socket.make_ref(),
+            socket,
+            topic,
+            spy,
+            broadcasts: Mailbox::new(rx),
+        }
+    }
+
+    /// The channel's own socket, as left by `join` and the event handlers.
+    pub async fn socket(&self) -> Option<Socket> {
+        let connection = self.socket.inner.connection.lock().await;
+        connection.channel_socket(&self.topic)
+    }
+
+    /// Push an event to the channel, returning the ref to pass to [`Self::assert_reply`].
+    pub async fn push(&self, event: &str, payload: Value) -> String {
+        self.send(event.into(), payload).await
+    }
+
+    pub async fn leave(&self) -> String {
+        self.send(Event::Leave, json!({})).await
+    }
+
+    /// Take the reply to `message_ref` and return its `response`, panicking if
+    /// there is none or its status is not `status`.
+    #[track_caller]
+    pub fn assert_reply(&self, message_ref: &str, status: &str) -> Value {
+        let Some(message) =
+            self.take(|m| m.event == Event::Reply && m.message_ref.as_deref() == Some(message_ref))
+        else {
+            panic!("no reply to ref {message_ref}, got: {}", self.pending());
+        };
+
+        let mut reply = Value::from(message.payload);
+        assert_eq!(reply["status"], status, "unexpected reply {reply}");
+
+        reply["response"].take()
+    }
+
+    /// Take the oldest message pushed on the topic with the given event and return
+    /// its payload, panicking if there is none.
+    #[track_caller]
+    pub fn assert_push(&self, event: &str) -> Value {
+        let event = Event::from(event);
+
+        match self.take(|m| m.event == event) {
+            Some(message) => unwrap_payload(message.payload.into()),
+            None => panic!(
+                "no push of {event} on {}, got: {}",
+                &*self.topic,
+                self.pending()
+            ),
+        }
+    }
+
+    /// Panic if a message with the given event was pushed on the topic.
+    #[track_caller]
+    pub fn refute_push(&self, event: &str) {
+        let event = Event::from(event);
+
+        if let Some(message) = self.take(|m| m.event == event) {
+            panic!("unexpected push {message}");
+        }
+    }
+
+    /// Take the oldest broadcast on the topic with the given event and return its
+    /// payload, panicking if there is none.
+    #[track_caller]
+    pub fn assert_broadcast(&mut self, event: &str) -> Value {
+        let event = Event::from(event);
+        let message = self.broadcasts.take(|m| m.event == event);
+
+        match message {
+            Some(message) => unwrap_payload(message.payload.into()),
+            None => panic!(
+                "no broadcast of {event} on {}, got: {}",
+                &*self.topic,
+                self.broadcasts.pending()
+            ),
+        }
+    }
+
+    async fn send(&self, event: Event, payload: Value) -> String {
+        let message_ref = self.socket.make_ref();
+        let message = Message {
+            join_ref: Some(self.join_ref.clone()),
+            message_ref: Some(message_ref.clone()),
+            topic: self.topic.clone(),
+            event,
+            payload: payload.into(),
+        };
+
+        let mut connection = self.socket.inner.connection.lock().await;
+        connection
+            .handle_text(&message.to_string())
+            .await
+            .expect("test message should be handled");
+
+        message_ref
+    }
+
+    fn take(&self, matches: impl Fn(&Message) -> bool) -> Option<Message> {
+        let mut mailbox = self.socket.inner.mailbox.lock().unwrap();
+        mailbox.take(|m| m.topic == self.topic && matches(m))
+    }
+
+    fn pending(&self) -> String {
+        self.socket.inner.mailbox.lock().unwrap().pending()
+    }
+}
+
+impl<T> Drop for TestChannel<T> {
+    fn drop(&mut self) {
+        WEBSOCKET_STATE.clearn_user(&self.spy);
+    }
+}
+
+impl<T> TestSocket<T> {
+    fn make_ref(&self) -> String {
+        (self.inner.refs.fetch_add(1, Ordering::Relaxed) + 1).to_string()
+    }
+}
+
+/// Decoded frames of an outbound queue, kept until an assertion takes them.
+struct Mailbox {
+    rx: OutboundReceiver,
+    messages: VecDeque<Message>,
+}
+
+impl Mailbox {
+    fn new(rx: OutboundReceiver) -> Self {
+        Self {
+            rx,
+            messages: VecDeque::new(),
+        }
+    }
+
+    fn take(&mut self, matches: impl Fn(&Message) -> bool) -> Option<Message> {
+        while let Some(frame) = self.rx.try_recv() {
+            if let Ok(message) = Serializer::V2.decode(frame.as_str()) {
+                self.messages.push_back(message);
+            }
+        }
+
+        let index = self.messages.iter().position(matches)?;
+        self.messages.remove(index)
+    }
+
+    fn pending(&mut self) -> String {
+        let _ = self.take(|_| false);
+        let messages = self
+            .messages
+            .iter()
+            .map(|m| m.to_string())
+            .collect::<Vec<_>>();
+
+        format!("[{}]", messages.join(", "))
+    }
+}
+
+/// Pushes and broadcasts are sent as `{"status", "response"}`, assertions look at the response.
+fn unwrap_payload(mut payload: Value) -> Value {
+    if payload.get("status").is_some() {
+        payload["response"].take()
+    } else {
+        payload
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{handler::Response, Channel, Payload};
+
+    async fn join(topic: Topic, _payload: Payload, socket: Socket) -> anyhow::Result<Value> {
+        if &*topic == "room:private" {
+            anyhow::bail!("unauthorized");
+        }
+
+        socket.lock().await.assigns.insert("joined", true);
+        Ok(json!({}))
+    }
+
+    async fn ping(payload: Payload, socket: Socket) -> Response {
+        let socket = socket.lock().await;
+        socket.push("pong", Ok(payload.into())).await.unwrap();
+
+        Response::Ok(json!({"pinged": true}))
+    }
+
+    #[derive(Default)]
+    struct Broadcasts;
+
+    fn websocket<T: Default + Send + Sync + 'static>(path: &str) -> WebSocket<T> {
+        WebSocket::new(path).channel("room:*", Channel::new().join(join).handler("ping", ping))
+    }
+
+    #[tokio::test]
+    async fn channel_test_should_assert_replies_and_pushes() {
+        let socket = ChannelTest::new(websocket::<()>("/test_util_push"))
+            .connect(json!({}))
+            .await
+            .unwrap();
+
+        let (_, room) = socket
+            .subscribe_and_join("room:1", json!({}))
+            .await
+            .unwrap();
+        let channel_socket = room.socket().await.unwrap();
+        assert_eq!(
+            channel_socket.lock().await.assigns.get::<bool>("joined"),
+            Some(&true)
+        );
+
+        let message_ref = room.push("ping", json!({"n": 1})).await;
+        assert_eq!(room.assert_push("pong"), json!({"n": 1}));
+        assert_eq!(
+            room.assert_reply(&message_ref, "ok"),
+            json!({"pinged": true})
+        );
+        room.refute_push("pong");
+
+        let err = socket.subscribe_and_join("room:private", json!({})).await;
+        assert!(err.is_err());
+    }
+
+    #[tokio::test]
+    async fn channel_test_should_assert_broadcasts() {
+        let test = ChannelTest::new(websocket::<Broadcasts>("/test_util_broadcast"));
+        let alice = test.connect(json!({})).await.unwrap();
+        let bob = test.connect(json!({})).await.unwrap();
+
+        let (_, mut alice_room) = alice.subscribe_and_join("room:1", json!({})).await.unwrap();
+        let (_, bob_room) = bob.subscribe_and_join("room:1", json!({})).await.unwrap();
+
+        let report = WebSocket::<Broadcasts>::broadcast("room:1", "news", Ok(json!(1)))
+            .await
+            .unwrap();
+        assert_eq!(report.targeted, 2);
+
+        assert_eq!(alice_room.assert_broadcast("news"), json!(1));
+        assert_eq!(bob_room.assert_push("news"), json!(1));
+
+        let report =
+            WebSocket::<Broadcasts>::broadcast_filter("room:1", "secret", Ok(json!(2)), |_| false)
+                .await
+                .unwrap();
+        assert_eq!(report.targeted, 0);
+
+        assert_eq!(alice_room.assert_broadcast("secret"), json!(2));
+        bob_room.refute_push("secret");
+
+        bob_room.leave().await;
+        bob_room.refute_push("news");
+    }
+}
+
+/// The endpoint, join and client most of the crate's own tests start from.
+#[cfg(test)]
+pub(crate) mod fixture {
+    use super::{ChannelTest, TestSocket};
+    use crate::{Payload, Socket, Topic, WebSocket};
+    use serde_json::{json, Value};
+
+    /// Endpoint type of the tests, which keep apart by path. Tests calling the
+    /// functions that find an endpoint by its type, e.g. `WebSocket::connection_count`,
+    /// need a type of their own.
+    #[derive(Default)]
+    pub(crate) struct Endpoint;
+
+    /// Accepts every join with an empty reply.
+    pub(crate) async fn join(
+        _topic: Topic,
+        _payload: Payload,
+        _socket: Socket,
+    ) -> anyhow::Result<Value> {
+        Ok(json!({}))
+    }
+
+    /// Connect a client without params to `websocket`.
+    pub(crate) async fn connect<T>(websocket: WebSocket<T>) -> TestSocket<T>
+    where
+        T: Default + Send + Sync + 'static,
+    {
+        ChannelTest::new(websocket)
+            .connect(json!({}))
+            .await
+            .unwrap()
+    }
+}
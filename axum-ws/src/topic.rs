@@ -4,27 +4,13 @@ use std::{
     ops::Deref,
 };
 
+use serde::Serialize;
 use serde_json::Value;
 
-#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(transparent)]
 pub struct Topic(String);
 
-impl Topic {
-    pub(crate) fn is_match(&self, other: &Topic) -> bool {
-        let mut topic_parts = self.split(':');
-        let mut other_parts = other.split(':');
-
-        loop {
-            match (topic_parts.next(), other_parts.next()) {
-                (Some("*"), _) => return true,
-                (Some(self_part), Some(topic_part)) if self_part == topic_part => continue,
-                (None, None) => return true,
-                _ => return false,
-            }
-        }
-    }
-}
-
 impl Hash for Topic {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.0.hash(state);
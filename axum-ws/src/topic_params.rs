@@ -0,0 +1,334 @@
+use serde::{
+    de::{self, value::MapDeserializer, DeserializeOwned, IntoDeserializer, SeqAccess, Visitor},
+    forward_to_deserialize_any, Deserializer,
+};
+use std::{fmt, ops::Deref};
+
+/// Segments captured by `{name}` and `*` in a channel's topic pattern, in order.
+///
+/// Segments matched by `*` are kept for positional access but have no name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Params(Vec<(Option<String>, String)>);
+
+impl Params {
+    pub(crate) fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(n, _)| n.as_deref() == Some(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub(crate) fn deserialize<T: DeserializeOwned>(&self) -> Result<T, ParamsError> {
+        T::deserialize(ParamsDeserializer(self))
+    }
+}
+
+impl FromIterator<(Option<String>, String)> for Params {
+    fn from_iter<I: IntoIterator<Item = (Option<String>, String)>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+/// Topic parameters deserialized into `T`, the channel counterpart of axum's `Path`.
+///
+/// `T` can be a struct or map keyed by parameter name, a tuple of every captured
+/// segment in order, or a single value when the pattern captures exactly one segment.
+///
+/// # Example
+///
+/// ```
+/// use axum_ws::{Payload, Socket, Topic, TopicParams};
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct RoomParams {
+///     room_id: u64,
+///     user_id: String,
+/// }
+///
+/// // registered as `.channel("room:{room_id}:user:{user_id}", Channel::new().join_with_params(join))`
+/// async fn join(
+///     _topic: Topic,
+///     TopicParams(params): TopicParams<RoomParams>,
+///     _payload: Payload,
+///     _socket: Socket,
+/// ) -> anyhow::Result<()> {
+///     println!("{} joined room {}", params.user_id, params.room_id);
+///     Ok(())
+/// }
+/// ```
+///
+/// Callbacks without a `_with_params` builder read them through [`Socket::topic_params`](crate::socket::Socket::topic_params).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TopicParams<T>(pub T);
+
+impl<T> Deref for TopicParams<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid topic params: {0}")]
+pub struct ParamsError(String);
+
+impl de::Error for ParamsError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+struct ParamsDeserializer<'a>(&'a Params);
+
+/// Scalars are only accepted when the pattern captured exactly one segment.
+macro_rules! single_value {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                match self.0 .0.as_slice() {
+                    [(_, value)] => ValueDeserializer(value).$method(visitor),
+                    params => Err(de::Error::invalid_length(params.len(), &"one topic param")),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de, 'a> Deserializer<'de> for ParamsDeserializer<'a> {
+    type Error = ParamsError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let named = self
+            .0
+             .0
+            .iter()
+            .filter_map(|(name, value)| Some((name.as_deref()?, ValueDeserializer(value))));
+
+        visitor.visit_map(MapDeserializer::new(named))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_seq(ValuesAccess(self.0 .0.iter()))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        if len != self.0.len() {
+            return Err(de::Error::invalid_length(self.0.len(), &visitor));
+        }
+
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    single_value! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_i128 deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64
+        deserialize_u128 deserialize_f32 deserialize_f64 deserialize_char deserialize_str
+        deserialize_string deserialize_option
+    }
+
+    forward_to_deserialize_any! {
+        bytes byte_buf unit unit_struct enum identifier ignored_any
+    }
+}
+
+struct ValuesAccess<'a>(std::slice::Iter<'a, (Option<String>, String)>);
+
+impl<'de, 'a> SeqAccess<'de> for ValuesAccess<'a> {
+    type Error = ParamsError;
+
+    fn next_element_seed<S: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: S,
+    ) -> Result<Option<S::Value>, Self::Error> {
+        self.0
+            .next()
+            .map(|(_, value)| seed.deserialize(ValueDeserializer(value)))
+            .transpose()
+    }
+}
+
+/// A single captured segment, parsed on demand into whatever type the field asks for.
+struct ValueDeserializer<'a>(&'a str);
+
+impl<'de, 'a> IntoDeserializer<'de, ParamsError> for ValueDeserializer<'a> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        self
+    }
+}
+
+macro_rules! parse_value {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                match self.0.parse() {
+                    Ok(v) => visitor.$visit(v),
+                    Err(_) => Err(de::Error::invalid_value(de::Unexpected::Str(self.0), &visitor)),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de, 'a> Deserializer<'de> for ValueDeserializer<'a> {
+    type Error = ParamsError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_str(self.0)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_enum(self.0.into_deserializer())
+    }
+
+    parse_value! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    forward_to_deserialize_any! {
+        str string bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::collections::HashMap;
+
+    fn params() -> Params {
+        [
+            (Some("room_id".to_string()), "42".to_string()),
+            (None, "lobby".to_string()),
+            (Some("user_id".to_string()), "u7".to_string()),
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    #[test]
+    fn params_should_deserialize_into_struct() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct RoomParams {
+            room_id: u64,
+            user_id: String,
+        }
+
+        let room: RoomParams = params().deserialize().unwrap();
+        assert_eq!(
+            room,
+            RoomParams {
+                room_id: 42,
+                user_id: "u7".to_string()
+            }
+        );
+
+        let map: HashMap<String, String> = params().deserialize().unwrap();
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn params_should_deserialize_into_tuple() {
+        let (room_id, name, user_id): (u32, String, String) = params().deserialize().unwrap();
+        assert_eq!(
+            (room_id, name.as_str(), user_id.as_str()),
+            (42, "lobby", "u7")
+        );
+
+        assert!(params().deserialize::<(u32, String)>().is_err());
+    }
+
+    #[test]
+    fn params_should_deserialize_single_value() {
+        let params: Params = [(Some("id".to_string()), "7".to_string())]
+            .into_iter()
+            .collect();
+
+        assert_eq!(params.deserialize::<u8>().unwrap(), 7);
+        assert!(self::params().deserialize::<u8>().is_err());
+    }
+
+    #[test]
+    fn params_should_report_invalid_values() {
+        #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct UserParams {
+            user_id: u64,
+        }
+
+        let err = params().deserialize::<UserParams>().unwrap_err();
+        assert!(err.to_string().contains("u7"));
+    }
+}
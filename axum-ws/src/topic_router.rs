@@ -0,0 +1,238 @@
+use crate::{topic_params::Params, websocket_error::WebSocketError};
+use std::collections::HashMap;
+
+/// Trie of topic patterns split on `:`.
+///
+/// A segment is either a literal, a `{name}` capture, or a `*` that matches a
+/// single segment (or any number of trailing segments when it comes last).
+///
+/// Lookups prefer the most specific pattern: a literal segment wins over a
+/// capture, a capture wins over a trailing `*`, and a longer literal prefix wins
+/// over a shorter one, so the result never depends on registration order.
+pub(crate) struct TopicRouter<T> {
+    root: Node<T>,
+}
+
+pub(crate) struct Match<'a, T> {
+    pub(crate) value: &'a T,
+    pub(crate) params: Params,
+    /// The pattern the topic was routed by.
+    pub(crate) pattern: &'a str,
+}
+
+struct Node<T> {
+    children: HashMap<String, Node<T>>,
+    param: Option<Box<Node<T>>>,
+    value: Option<Route<T>>,
+    wildcard: Option<Route<T>>,
+}
+
+struct Route<T> {
+    value: T,
+    names: Vec<Option<String>>,
+    pattern: String,
+}
+
+impl<T> Default for Node<T> {
+    fn default() -> Self {
+        Self {
+            children: HashMap::new(),
+            param: None,
+            value: None,
+            wildcard: None,
+        }
+    }
+}
+
+impl<T> Default for TopicRouter<T> {
+    fn default() -> Self {
+        Self {
+            root: Node::default(),
+        }
+    }
+}
+
+impl<T> TopicRouter<T> {
+    pub(crate) fn insert(&mut self, pattern: &str, value: T) -> Result<(), WebSocketError> {
+        let invalid = || WebSocketError::InvalidTopicPattern(pattern.to_string());
+        let parts = pattern.split(':').collect::<Vec<_>>();
+        let mut names = vec![];
+        let mut node = &mut self.root;
+
+        for (i, part) in parts.iter().enumerate() {
+            if *part == "*" && i == parts.len() - 1 {
+                let route = Route {
+                    value,
+                    names,
+                    pattern: pattern.to_string(),
+                };
+                return Self::set(&mut node.wildcard, pattern, route);
+            }
+
+            if *part == "*" {
+                names.push(None);
+                node = node.param.get_or_insert_with(Box::default);
+            } else if let Some(name) = part.strip_prefix('{') {
+                let name = name.strip_suffix('}').ok_or_else(invalid)?;
+
+                if name.is_empty() || names.iter().flatten().any(|n| n == name) {
+                    return Err(invalid());
+                }
+
+                names.push(Some(name.to_string()));
+                node = node.param.get_or_insert_with(Box::default);
+            } else if part.contains(['{', '}', '*']) {
+                return Err(invalid());
+            } else {
+                node = node.children.entry(part.to_string()).or_default();
+            }
+        }
+
+        let route = Route {
+            value,
+            names,
+            pattern: pattern.to_string(),
+        };
+        Self::set(&mut node.value, pattern, route)
+    }
+
+    pub(crate) fn at(&self, topic: &str) -> Option<Match<'_, T>> {
+        let parts = topic.split(':').collect::<Vec<_>>();
+        let mut captured = vec![];
+
+        Self::find(&self.root, &parts, &mut captured).map(|route| Match {
+            value: &route.value,
+            pattern: &route.pattern,
+            params: route
+                .names
+                .iter()
+                .cloned()
+                .zip(captured.into_iter().map(|s| s.to_string()))
+                .collect(),
+        })
+    }
+
+    fn set(
+        slot: &mut Option<Route<T>>,
+        pattern: &str,
+        route: Route<T>,
+    ) -> Result<(), WebSocketError> {
+        if slot.is_some() {
+            return Err(WebSocketError::TopicConflict(pattern.to_string()));
+        }
+
+        *slot = Some(route);
+        Ok(())
+    }
+
+    fn find<'a, 'b>(
+        node: &'a Node<T>,
+        parts: &[&'b str],
+        captured: &mut Vec<&'b str>,
+    ) -> Option<&'a Route<T>> {
+        let Some((part, rest)) = parts.split_first() else {
+            return node.value.as_ref().or(node.wildcard.as_ref());
+        };
+
+        if let Some(route) = node
+            .children
+            .get(*part)
+            .and_then(|child| Self::find(child, rest, captured))
+        {
+            return Some(route);
+        }
+
+        if let Some(param) = node.param.as_ref() {
+            captured.push(part);
+
+            if let Some(route) = Self::find(param, rest, captured) {
+                return Some(route);
+            }
+
+            captured.pop();
+        }
+
+        node.wildcard.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn router(patterns: &[&'static str]) -> TopicRouter<&'static str> {
+        let mut router = TopicRouter::default();
+
+        for pattern in patterns {
+            router.insert(pattern, *pattern).unwrap();
+        }
+
+        router
+    }
+
+    fn at(router: &TopicRouter<&'static str>, topic: &str) -> Option<&'static str> {
+        router.at(topic).map(|m| *m.value)
+    }
+
+    #[test]
+    fn topic_router_should_prefer_specific_patterns() {
+        let router = router(&["room:*", "room:lobby", "room:lobby:*", "*"]);
+
+        assert_eq!(at(&router, "room:lobby"), Some("room:lobby"));
+        assert_eq!(at(&router, "room:lobby:1"), Some("room:lobby:*"));
+        assert_eq!(at(&router, "room:1"), Some("room:*"));
+        assert_eq!(at(&router, "room"), Some("room:*"));
+        assert_eq!(at(&router, "user:1"), Some("*"));
+    }
+
+    #[test]
+    fn topic_router_should_not_match_partial_topics() {
+        let router = router(&["room:lobby"]);
+
+        assert_eq!(at(&router, "room"), None);
+        assert_eq!(at(&router, "room:lobby:1"), None);
+    }
+
+    #[test]
+    fn topic_router_should_capture_params() {
+        let router = router(&["room:{room_id}:user:{user_id}", "room:*:admin", "room:*"]);
+
+        let m = router.at("room:42:user:7").unwrap();
+        assert_eq!(*m.value, "room:{room_id}:user:{user_id}");
+        assert_eq!(m.params.get("room_id"), Some("42"));
+        assert_eq!(m.params.get("user_id"), Some("7"));
+
+        let m = router.at("room:42:admin").unwrap();
+        assert_eq!(*m.value, "room:*:admin");
+        assert_eq!(m.params.len(), 1);
+
+        let m = router.at("room:42:user").unwrap();
+        assert_eq!(*m.value, "room:*");
+        assert_eq!(m.params.len(), 0);
+    }
+
+    #[test]
+    fn topic_router_should_reject_conflicts() {
+        let mut router = router(&["room:*", "room:lobby", "room:{id}"]);
+
+        assert!(matches!(
+            router.insert("room:*", ""),
+            Err(WebSocketError::TopicConflict(_))
+        ));
+        assert!(matches!(
+            router.insert("room:lobby", ""),
+            Err(WebSocketError::TopicConflict(_))
+        ));
+        assert!(matches!(
+            router.insert("room:{room_id}", ""),
+            Err(WebSocketError::TopicConflict(_))
+        ));
+
+        for pattern in ["room:{id", "room:{}", "room:{id}:{id}", "room:a*"] {
+            assert!(matches!(
+                router.insert(pattern, ""),
+                Err(WebSocketError::InvalidTopicPattern(_))
+            ));
+        }
+    }
+}
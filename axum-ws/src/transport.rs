@@ -0,0 +1,476 @@
+use crate::{
+    connection::Connection, serializer::Serializer, shutdown, user_id::UserId,
+    websocket::WebSocket, websocket_error::WebSocketError, websocket_state::WEBSOCKET_STATE,
+    Socket,
+};
+use axum::extract::ws::{self, close_code};
+use futures::{
+    channel::mpsc,
+    future,
+    stream::{BoxStream, Stream},
+    Sink, SinkExt, StreamExt,
+};
+use serde_json::Value;
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tokio::time::Instant;
+use tracing::Instrument;
+
+/// A frame exchanged with a client over a [`Transport`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransportMessage {
+    /// An encoded channel message.
+    Text(String),
+    /// The closing handshake, with an optional status code and reason.
+    Close(Option<CloseFrame>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseFrame {
+    pub code: u16,
+    pub reason: String,
+}
+
+/// Sending half of a [`Transport`].
+pub type TransportSink<E> = Pin<Box<dyn Sink<TransportMessage, Error = E> + Send>>;
+
+/// Receiving half of a [`Transport`].
+pub type TransportStream<E> = BoxStream<'static, Result<TransportMessage, E>>;
+
+/// A bidirectional connection the channel engine can be driven over.
+///
+/// Implemented for axum's WebSocket, for tokio-tungstenite streams with the
+/// `tungstenite` feature, and for the in-memory [`MemoryTransport`]. Anything else,
+/// such as a hyper upgrade, can implement it and be run with [`Accepted::serve`].
+///
+/// Control frames such as pings are the transport's business and should not be
+/// yielded by the stream.
+pub trait Transport: Send + 'static {
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    fn split(self) -> (TransportSink<Self::Error>, TransportStream<Self::Error>);
+}
+
+/// A client authenticated by the endpoint's `connect` and `id` callbacks, ready to
+/// be served over a transport.
+pub struct Accepted<T> {
+    websocket: Arc<WebSocket<T>>,
+    socket: Socket,
+    serializer: Serializer,
+}
+
+impl<T> WebSocket<T>
+where
+    T: Default + Send + Sync + 'static,
+{
+    /// Authenticate a client with its connect params, `vsn` selecting the serializer.
+    ///
+    /// A client or server error returned by `connect` rejects the client and is
+    /// handed back as is, so it can be answered before upgrading the connection.
+    pub async fn accept(
+        self: Arc<Self>,
+        params: Value,
+    ) -> Result<Accepted<T>, axum::response::Response> {
+        let serializer = Serializer::from_vsn(params.get("vsn").and_then(Value::as_str));
+        let socket = Connection::authenticate(&self, params).await?;
+
+        Ok(Accepted {
+            websocket: self,
+            socket,
+            serializer,
+        })
+    }
+}
+
+impl<T> Accepted<T>
+where
+    T: Default + Send + Sync + 'static,
+{
+    /// Run the client's channels over the transport until either side closes it,
+    /// or [`WebSocket::shutdown`] closes it with `1001 Going Away`.
+    pub async fn serve(self, transport: impl Transport) {
+        let (mut sender, mut receiver) = transport.split();
+        let user_id: UserId = self.socket.lock().await.connection_id.clone().into();
+        let shutdown = WEBSOCKET_STATE.get_shutdown(&self.websocket.path);
+        let mut recv_signal = shutdown.as_ref().map(|shutdown| shutdown.subscribe());
+        let mut send_signal = shutdown.as_ref().map(|shutdown| shutdown.subscribe());
+        let timeout = shutdown.as_ref().map(|shutdown| shutdown.timeout());
+        let (connection, mut rx) =
+            Connection::open(self.websocket, self.socket, self.serializer).await;
+        let span = connection.span().clone();
+        // Shared with the receive task so that channels are terminated however it ends.
+        let connection = Arc::new(tokio::sync::Mutex::new(connection));
+
+        let mut recv_task = tokio::spawn({
+            let user_id = user_id.clone();
+            let connection = connection.clone();
+
+            async move {
+                let reason = loop {
+                    tokio::select! {
+                        message = receiver.next() => match message {
+                            Some(Ok(TransportMessage::Text(message))) => {
+                                connection.lock().await.handle_text(&message).await?;
+                            }
+                            Some(Err(_)) => break "transport error",
+                            _ => break "client closed",
+                        },
+                        _ = shutdown::signalled(&mut recv_signal) => {
+                            connection.lock().await.terminate().await;
+
+                            // Let the send task flush what is left, then close.
+                            if let Some(tx) = WEBSOCKET_STATE.get_sender(&user_id) {
+                                tx.close();
+                            }
+
+                            return Ok(None);
+                        }
+                    }
+                };
+
+                Ok::<_, WebSocketError>(Some(reason))
+            }
+            .instrument(span.clone())
+        });
+
+        let mut send_task = tokio::spawn(
+            async move {
+                let mut deadline = None;
+
+                loop {
+                    let frame = match deadline {
+                        None => tokio::select! {
+                            frame = rx.recv() => frame,
+                            _ = shutdown::signalled(&mut send_signal) => {
+                                deadline = timeout.map(|timeout| Instant::now() + timeout);
+                                continue;
+                            }
+                        },
+                        Some(deadline) => tokio::time::timeout_at(deadline, rx.recv())
+                            .await
+                            .ok()
+                            .flatten(),
+                    };
+
+                    let Some(frame) = frame else {
+                        break;
+                    };
+
+                    sender
+                        .send(TransportMessage::Text(frame.as_str().to_owned()))
+                        .await
+                        .map_err(anyhow::Error::from)?;
+                }
+
+                let (frame, reason) = if rx.overflowed() {
+                    let frame = CloseFrame {
+                        code: close_code::AGAIN,
+                        reason: "slow consumer".to_string(),
+                    };
+                    (Some(frame), "slow consumer")
+                } else if deadline.is_some() {
+                    let frame = CloseFrame {
+                        code: close_code::AWAY,
+                        reason: "server shutting down".to_string(),
+                    };
+                    (Some(frame), "shutdown")
+                } else {
+                    (None, "closed")
+                };
+
+                if let Some(frame) = frame {
+                    sender
+                        .send(TransportMessage::Close(Some(frame)))
+                        .await
+                        .map_err(anyhow::Error::from)?;
+                }
+
+                Ok::<_, WebSocketError>(reason)
+            }
+            .instrument(span.clone()),
+        );
+
+        let reason = tokio::select! {
+            res = (&mut send_task) => {
+                recv_task.abort();
+
+                match res {
+                    Ok(Ok(reason)) => reason,
+                    _ => "transport error",
+                }
+            }
+            res = (&mut recv_task) => match res {
+                Ok(Ok(None)) => {
+                    let timeout = timeout.unwrap_or_default();
+
+                    if tokio::time::timeout(timeout, &mut send_task).await.is_err() {
+                        send_task.abort();
+                    }
+
+                    "shutdown"
+                }
+                Ok(Ok(Some(reason))) => {
+                    send_task.abort();
+                    reason
+                }
+                _ => {
+                    send_task.abort();
+                    "error"
+                }
+            }
+        };
+
+        // A no-op after a shutdown, which terminates before draining the queue.
+        connection.lock().await.terminate().await;
+
+        tracing::info!(parent: &span, reason, "socket disconnected");
+
+        WEBSOCKET_STATE.clearn_user(&user_id);
+    }
+}
+
+impl Transport for ws::WebSocket {
+    type Error = axum::Error;
+
+    fn split(self) -> (TransportSink<Self::Error>, TransportStream<Self::Error>) {
+        let (sink, stream) = StreamExt::split(self);
+
+        let sink = sink.with(|message| {
+            future::ready(Ok(match message {
+                TransportMessage::Text(text) => ws::Message::Text(text),
+                TransportMessage::Close(frame) => {
+                    ws::Message::Close(frame.map(|frame| ws::CloseFrame {
+                        code: frame.code,
+                        reason: frame.reason.into(),
+                    }))
+                }
+            }))
+        });
+
+        let stream = stream.filter_map(|message| {
+            future::ready(match message {
+                Ok(ws::Message::Text(text)) => Some(Ok(TransportMessage::Text(text))),
+                Ok(ws::Message::Close(frame)) => {
+                    Some(Ok(TransportMessage::Close(frame.map(|frame| CloseFrame {
+                        code: frame.code,
+                        reason: frame.reason.into_owned(),
+                    }))))
+                }
+                Ok(_) => None,
+                Err(err) => Some(Err(err)),
+            })
+        });
+
+        (Box::pin(sink), stream.boxed())
+    }
+}
+
+#[cfg(feature = "tungstenite")]
+impl<S> Transport for tokio_tungstenite::WebSocketStream<S>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+{
+    type Error = tokio_tungstenite::tungstenite::Error;
+
+    fn split(self) -> (TransportSink<Self::Error>, TransportStream<Self::Error>) {
+        use tokio_tungstenite::tungstenite::protocol::{self, frame::coding::CloseCode};
+
+        let (sink, stream) = StreamExt::split(self);
+
+        let sink = sink.with(|message| {
+            future::ready(Ok(match message {
+                TransportMessage::Text(text) => protocol::Message::Text(text),
+                TransportMessage::Close(frame) => {
+                    protocol::Message::Close(frame.map(|frame| protocol::CloseFrame {
+                        code: CloseCode::from(frame.code),
+                        reason: frame.reason.into(),
+                    }))
+                }
+            }))
+        });
+
+        let stream = stream.filter_map(|message| {
+            future::ready(match message {
+                Ok(protocol::Message::Text(text)) => Some(Ok(TransportMessage::Text(text))),
+                Ok(protocol::Message::Close(frame)) => {
+                    Some(Ok(TransportMessage::Close(frame.map(|frame| CloseFrame {
+                        code: frame.code.into(),
+                        reason: frame.reason.into_owned(),
+                    }))))
+                }
+                Ok(_) => None,
+                Err(err) => Some(Err(err)),
+            })
+        });
+
+        (Box::pin(sink), stream.boxed())
+    }
+}
+
+/// One end of an in-memory connection, created in pairs by [`MemoryTransport::pair`].
+///
+/// Serve one end and drive the other as a client through its `Stream` and `Sink`
+/// implementations, e.g. to test channels without a network.
+pub struct MemoryTransport {
+    tx: mpsc::UnboundedSender<TransportMessage>,
+    rx: mpsc::UnboundedReceiver<TransportMessage>,
+}
+
+impl MemoryTransport {
+    pub fn pair() -> (Self, Self) {
+        let (a_tx, a_rx) = mpsc::unbounded();
+        let (b_tx, b_rx) = mpsc::unbounded();
+
+        (Self { tx: a_tx, rx: b_rx }, Self { tx: b_tx, rx: a_rx })
+    }
+}
+
+impl Stream for MemoryTransport {
+    type Item = TransportMessage;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.rx).poll_next(cx)
+    }
+}
+
+impl Sink<TransportMessage> for MemoryTransport {
+    type Error = mpsc::SendError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.tx).poll_ready(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: TransportMessage) -> Result<(), Self::Error> {
+        Pin::new(&mut self.tx).start_send(item)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.tx).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.tx).poll_close(cx)
+    }
+}
+
+impl Transport for MemoryTransport {
+    type Error = mpsc::SendError;
+
+    fn split(self) -> (TransportSink<Self::Error>, TransportStream<Self::Error>) {
+        (Box::pin(self.tx), self.rx.map(Ok).boxed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_util::fixture::{self, join},
+        Channel, OverflowPolicy, Payload, Topic,
+    };
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn memory_transport_should_serve_channels() {
+        async fn join(_topic: Topic, _payload: Payload, _socket: Socket) -> anyhow::Result<Value> {
+            Ok(json!({"joined": true}))
+        }
+
+        let websocket =
+            WebSocket::<()>::new("/transport").channel("room:*", Channel::new().join(join));
+        let accepted = Arc::new(websocket)
+            .accept(json!({"vsn": "2.0.0"}))
+            .await
+            .unwrap();
+
+        let (server, mut client) = MemoryTransport::pair();
+        let serve = tokio::spawn(accepted.serve(server));
+
+        let join = json!(["1", "1", "room:1", "phx_join", {}]).to_string();
+        client.send(TransportMessage::Text(join)).await.unwrap();
+
+        let Some(TransportMessage::Text(reply)) = client.next().await else {
+            panic!("expected a reply");
+        };
+        assert_eq!(
+            serde_json::from_str::<Value>(&reply).unwrap(),
+            json!(["1", "1", "room:1", "phx_reply", {"status": "ok", "response": {"joined": true}}])
+        );
+
+        client.send(TransportMessage::Close(None)).await.unwrap();
+        serve.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn serve_should_terminate_channels_of_slow_consumers() {
+        static TERMINATED: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Default)]
+        struct Endpoint;
+
+        async fn terminate(_topic: Topic, _socket: Socket) {
+            TERMINATED.fetch_add(1, Ordering::SeqCst);
+        }
+
+        let websocket = WebSocket::<Endpoint>::new("/transport_overflow")
+            .buffer_size(1)
+            .overflow_policy(OverflowPolicy::Disconnect)
+            .channel("room:*", Channel::new().join(join).terminate(terminate));
+        let accepted = Arc::new(websocket).accept(json!({})).await.unwrap();
+
+        let (server, mut client) = MemoryTransport::pair();
+        let serve = tokio::spawn(accepted.serve(server));
+
+        let join = json!(["1", "1", "room:1", "phx_join", {}]).to_string();
+        client.send(TransportMessage::Text(join)).await.unwrap();
+        assert!(matches!(
+            client.next().await,
+            Some(TransportMessage::Text(_))
+        ));
+
+        // Nothing is written in between, so the second one overflows the queue.
+        for _ in 0..2 {
+            WebSocket::<Endpoint>::broadcast("room:1", "flood", Ok(json!({})))
+                .await
+                .unwrap();
+        }
+
+        serve.await.unwrap();
+        assert_eq!(TERMINATED.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn serve_should_terminate_channels_after_a_bad_frame() {
+        static TERMINATED: AtomicUsize = AtomicUsize::new(0);
+
+        async fn terminate(_topic: Topic, _socket: Socket) {
+            TERMINATED.fetch_add(1, Ordering::SeqCst);
+        }
+
+        let websocket = WebSocket::<fixture::Endpoint>::new("/transport_bad_frame")
+            .channel("room:*", Channel::new().join(join).terminate(terminate));
+        let accepted = Arc::new(websocket).accept(json!({})).await.unwrap();
+
+        let (server, mut client) = MemoryTransport::pair();
+        let serve = tokio::spawn(accepted.serve(server));
+
+        let join = json!(["1", "1", "room:1", "phx_join", {}]).to_string();
+        client.send(TransportMessage::Text(join)).await.unwrap();
+        assert!(matches!(
+            client.next().await,
+            Some(TransportMessage::Text(_))
+        ));
+
+        client
+            .send(TransportMessage::Text("not a message".to_string()))
+            .await
+            .unwrap();
+
+        serve.await.unwrap();
+        assert_eq!(TERMINATED.load(Ordering::SeqCst), 1);
+    }
+}
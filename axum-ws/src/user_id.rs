@@ -3,6 +3,7 @@ use std::{
     hash::{Hash, Hasher},
 };
 
+/// Key of a connection's registrations in the shared state, its connection id.
 #[derive(Clone, PartialEq, Eq)]
 pub(crate) struct UserId(String);
 
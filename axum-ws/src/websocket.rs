@@ -1,35 +1,45 @@
 use crate::{
+    broadcast::{Audience, BroadcastReport},
     channel::Channel,
-    event::Event,
-    handler::{Connect, ConnectWrapper, Id, IdWrapper},
-    handler::{IntoResponse, Response},
-    message::Message,
+    handler::{Authorize, AuthorizeWrapper, Connect, ConnectWrapper, Id, IdWrapper},
+    longpoll::{LongPoll, Sessions},
+    outbound::{OverflowPolicy, DEFAULT_BLOCK_TIMEOUT, DEFAULT_BUFFER_SIZE},
+    shutdown::{GracefulShutdown, Shutdown},
     socket,
+    sse::ServerSentEvents,
     topic::Topic,
-    websocket_error::WebSocketError,
-    websocket_state::{do_broadcast, WEBSOCKET_STATE},
+    topic_router::{Match, TopicRouter},
+    websocket_state::{do_broadcast, do_broadcast_many, do_push, WEBSOCKET_STATE},
     Socket,
 };
 use anyhow::Result;
 use axum::{
-    extract::{ws, Query, WebSocketUpgrade},
-    routing::get,
+    extract::{Query, WebSocketUpgrade},
+    http::HeaderMap,
+    routing::{get, post},
     Extension, Router,
 };
-use futures::{Future, SinkExt, StreamExt};
-use serde_json::{json, Value};
-use std::{collections::HashMap, marker::PhantomData, sync::Arc};
-use tokio::sync::{mpsc, Mutex};
-
-const USER_BUFFER_SIZE: usize = 1024;
+use futures::Future;
+use serde_json::Value;
+use std::{marker::PhantomData, sync::Arc, time::Duration};
 
 #[derive(Default)]
 
 pub struct WebSocket<T> {
-    path: String,
-    channels: HashMap<Topic, Channel>,
-    connect: Option<Box<dyn Connect + Send + Sync>>,
-    id: Option<Box<dyn Id + Send + Sync>>,
+    pub(crate) path: String,
+    channels: TopicRouter<Arc<Channel>>,
+    pub(crate) connect: Option<Box<dyn Connect + Send + Sync>>,
+    pub(crate) id: Option<Box<dyn Id + Send + Sync>>,
+    pub(crate) buffer_size: usize,
+    pub(crate) overflow_policy: OverflowPolicy,
+    pub(crate) block_timeout: Duration,
+    pub(crate) longpoll: Option<LongPoll>,
+    pub(crate) sessions: Sessions<T>,
+    pub(crate) sse: Option<ServerSentEvents>,
+    pub(crate) publish: Option<Box<dyn Authorize + Send + Sync>>,
+    pub(crate) admin: Option<Box<dyn Authorize + Send + Sync>>,
+    #[cfg(feature = "metrics")]
+    pub(crate) metrics: bool,
     _tag: PhantomData<T>,
 }
 
@@ -41,26 +51,80 @@ where
     pub fn new(path: impl Into<String>) -> Self {
         let path = path.into();
         WEBSOCKET_STATE.insert_path::<T>(path.clone());
+        WEBSOCKET_STATE.insert_shutdown(path.clone(), Shutdown::new(GracefulShutdown::new()));
 
         Self {
             path,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            block_timeout: DEFAULT_BLOCK_TIMEOUT,
             ..Default::default()
         }
     }
 
-    pub fn channel(mut self, topic: impl Into<Topic>, channel: Channel) -> Self {
-        self.channels.insert(topic.into(), channel);
+    /// Maximum number of messages queued for a single connection, defaults to 1024.
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
         self
     }
 
-    fn get_channel(&self, topic: &Topic) -> Option<&Channel> {
-        for (t, c) in &self.channels {
-            if t.is_match(topic) {
-                return Some(c);
-            }
+    /// How a connection whose queue is full is treated, see [`OverflowPolicy`].
+    pub fn overflow_policy(mut self, overflow_policy: OverflowPolicy) -> Self {
+        self.overflow_policy = overflow_policy;
+        self
+    }
+
+    /// How long a broadcast waits for room in a full queue under
+    /// [`OverflowPolicy::Block`] before dropping the message for that connection,
+    /// defaults to 5 seconds.
+    pub fn block_timeout(mut self, block_timeout: Duration) -> Self {
+        self.block_timeout = block_timeout;
+        self
+    }
+
+    /// Also serve the Phoenix LongPoll transport at `{path}/longpoll`, for clients
+    /// that cannot open a WebSocket.
+    pub fn longpoll(mut self, longpoll: LongPoll) -> Self {
+        self.longpoll = Some(longpoll);
+        self
+    }
+
+    /// Also serve a read-only Server-Sent Events stream of broadcasts at `{path}/sse`.
+    pub fn sse(mut self, sse: ServerSentEvents) -> Self {
+        WEBSOCKET_STATE.insert_history(self.path.clone(), sse.history());
+        self.sse = Some(sse);
+        self
+    }
+
+    /// How [`WebSocket::shutdown`] drains the endpoint, see [`GracefulShutdown`].
+    pub fn graceful_shutdown(self, graceful_shutdown: GracefulShutdown) -> Self {
+        WEBSOCKET_STATE.insert_shutdown(self.path.clone(), Shutdown::new(graceful_shutdown));
+        self
+    }
+
+    /// Register a channel for a topic pattern such as `"room:lobby"`, `"room:*"` or
+    /// `"room:{room_id}:user:{user_id}"`.
+    ///
+    /// `{name}` and a `*` before the last segment capture exactly one segment, which
+    /// handlers take as a [`TopicParams`](crate::TopicParams) argument, see
+    /// [`Channel::join_with_params`], or read through [`socket::Socket::topic_params`].
+    /// A topic is routed to the most specific matching pattern, literal segments
+    /// winning over captures.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the same pattern shape is already registered or the pattern is malformed.
+    pub fn channel(mut self, topic: impl Into<Topic>, channel: impl Into<Channel>) -> Self {
+        let topic = topic.into();
+
+        if let Err(err) = self.channels.insert(&topic, Arc::new(channel.into())) {
+            panic!("{err}");
         }
 
-        None
+        self
+    }
+
+    pub(crate) fn get_channel(&self, topic: &Topic) -> Option<Match<'_, Arc<Channel>>> {
+        self.channels.at(topic)
     }
 
     async fn upgrade(
@@ -68,208 +132,185 @@ where
         Query(params): Query<Value>,
         Extension(websocket): Extension<Arc<WebSocket<T>>>,
     ) -> axum::response::Response {
-        let mut user_id = nanoid::nanoid!();
-        let path = websocket.path.clone();
-        let socket = Arc::new(Mutex::new(socket::Socket::new(user_id.clone(), path)));
-        let shared_socket = socket.clone();
+        let accepted = match websocket.accept(params).await {
+            Ok(accepted) => accepted,
+            Err(res) => return res,
+        };
 
-        if let Some(connect) = websocket.connect.as_ref() {
-            let res = connect.call(params, shared_socket.clone()).await;
+        websocket_upgrade.on_upgrade(move |axum_websocket| accepted.serve(axum_websocket))
+    }
 
-            if res.status().is_client_error() || res.status().is_server_error() {
-                return res;
-            }
-        }
+    pub async fn broadcast(
+        topic: &str,
+        event: &str,
+        data: Result<Value>,
+    ) -> Result<BroadcastReport> {
+        Self::broadcast_to(Audience::default(), topic, event, data).await
+    }
 
-        if let Some(id) = websocket.id.as_ref() {
-            if let Some(gen_id) = id.call(shared_socket.clone()).await {
-                let mut socket = shared_socket.lock().await;
-                socket.set_id(gen_id.clone());
-                user_id = gen_id;
-            }
-        }
+    pub async fn broadcast_from(
+        user_id: &str,
+        topic: &str,
+        event: &str,
+        data: Result<Value>,
+    ) -> Result<BroadcastReport> {
+        Self::broadcast_except(&[user_id], topic, event, data).await
+    }
 
-        websocket_upgrade.on_upgrade(|axum_websocket| async move {
-            let (mut sender, mut receiver) = axum_websocket.split();
-            let (tx, mut rx) = mpsc::channel(USER_BUFFER_SIZE);
-
-            WEBSOCKET_STATE.insert_sender(user_id.clone(), tx);
-
-            let mut recv_task = tokio::spawn(async move {
-                let mut sockets = HashMap::<Topic, Socket>::new();
-
-                while let Some(Ok(ws::Message::Text(message))) = receiver.next().await {
-                    let message: Message = message.try_into()?;
-
-                    match message.event {
-                        Event::Join => {
-                            {
-                                let mut socket = shared_socket.lock().await;
-                                socket.set_message(message.clone());
-                            }
-
-                            let topic = message.topic.clone();
-
-                            if let Some(channel) = websocket.get_channel(&topic) {
-                                if let Some(join) = channel.join.as_ref() {
-                                    let res = join
-                                        .call(
-                                            topic.clone(),
-                                            message.payload.clone(),
-                                            shared_socket.clone(),
-                                        )
-                                        .await;
-
-                                    let socket = shared_socket.lock().await;
-
-                                    if res.is_ok() {
-                                        let mut socket = socket.clone();
-
-                                        socket.set_joined(true);
-                                        socket.set_topic(topic.clone());
-
-                                        WEBSOCKET_STATE.insert_user(
-                                            (websocket.path.clone(), topic.clone()),
-                                            socket.id.clone().into(),
-                                        );
-
-                                        sockets.insert(topic.clone(), Arc::new(Mutex::new(socket)));
-                                    }
-
-                                    let payload: Value = res.into_response().into();
-                                    let message = Message::builder()
-                                        .event("reply")
-                                        .payload(payload)
-                                        .build()
-                                        .unwrap();
-
-                                    socket.push_message(message).await?;
-                                }
-                            } else {
-                                let message = Message::builder()
-                                    .event("reply")
-                                    .payload(Response::Err("unmatched topic".into()))
-                                    .build()
-                                    .unwrap();
-
-                                let socket = shared_socket.lock().await;
-                                socket.push_message(message).await?;
-                            }
-                        }
-                        Event::Leave => {
-                            let mut socket = shared_socket.lock().await;
-                            let topic = message.topic.clone();
-                            socket.set_message(message.clone());
-
-                            let message = Message::builder()
-                                .event("reply")
-                                .payload(Response::Ok(json!({})))
-                                .build()
-                                .unwrap();
-
-                            socket.push_message(message).await?;
-
-                            let user_id = socket.id.clone().into();
-
-                            WEBSOCKET_STATE
-                                .remove_user(&(websocket.path.clone(), topic.clone()), &user_id);
-
-                            sockets.remove(&topic);
-
-                            let message = Message::builder()
-                                .event("close")
-                                .payload(Response::NoReply)
-                                .build()
-                                .unwrap();
-
-                            socket.push_message(message).await?;
-                        }
-                        Event::Heartbeat => {
-                            let mut socket = shared_socket.lock().await;
-                            socket.set_message(message.clone());
-
-                            let message = Message::builder()
-                                .event("heartbeat")
-                                .payload(Response::Ok(json!({})))
-                                .build()
-                                .unwrap();
-
-                            socket.push_message(message).await?;
-                        }
-                        Event::Custom(ref event) => {
-                            if let Some(socket) = sockets.get(&message.topic) {
-                                {
-                                    let mut socket = socket.lock().await;
-                                    socket.set_message(message.clone());
-                                }
-
-                                if let Some(channel) = websocket.get_channel(&message.topic) {
-                                    if let Some(handler) = channel.handler.get(event) {
-                                        let res = handler
-                                            .call(message.payload.clone(), socket.clone())
-                                            .await;
-
-                                        if res != Response::NoReply {
-                                            let payload: Value = res.into_response().into();
-                                            let message = Message::builder()
-                                                .event("reply")
-                                                .payload(payload)
-                                                .build()
-                                                .unwrap();
-
-                                            let socket = socket.lock().await;
-                                            socket.push_message(message).await?;
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        _ => {}
-                    }
-                }
-
-                Ok::<_, WebSocketError>(())
-            });
-
-            let mut send_task = tokio::spawn(async move {
-                while let Some(message) = rx.recv().await {
-                    sender.send(message.into()).await?;
-                }
-
-                Ok::<_, WebSocketError>(())
-            });
-
-            tokio::select! {
-                _ = (&mut send_task) => recv_task.abort(),
-                _ = (&mut recv_task) => send_task.abort(),
-            };
+    /// Broadcast to the subscribers of `topic` except the connections of the
+    /// given socket ids.
+    pub async fn broadcast_except(
+        exclude: &[&str],
+        topic: &str,
+        event: &str,
+        data: Result<Value>,
+    ) -> Result<BroadcastReport> {
+        Self::broadcast_to(Audience::except(exclude), topic, event, data).await
+    }
 
-            let user_id = socket.lock().await.id.clone().into();
-            WEBSOCKET_STATE.clearn_user(&user_id);
-        })
+    /// Broadcast to the subscribers of `topic` whose channel socket satisfies
+    /// `filter`, e.g. those with a `"moderator"` assign.
+    ///
+    /// The filter runs on every subscriber during the fan-out and must not block.
+    /// A socket whose lock is held by a running handler is judged as it was
+    /// after its join.
+    pub async fn broadcast_filter<F>(
+        topic: &str,
+        event: &str,
+        data: Result<Value>,
+        filter: F,
+    ) -> Result<BroadcastReport>
+    where
+        F: Fn(&socket::Socket) -> bool + Send + Sync,
+    {
+        Self::broadcast_to(Audience::filter(&filter), topic, event, data).await
     }
 
-    pub async fn broadcast(topic: &str, event: &str, data: Result<Value>) -> Result<()> {
-        if let Some(path) = WEBSOCKET_STATE.get_path::<T>() {
-            let topic: Topic = topic.into();
+    async fn broadcast_to(
+        audience: Audience<'_>,
+        topic: &str,
+        event: &str,
+        data: Result<Value>,
+    ) -> Result<BroadcastReport> {
+        let Some(path) = WEBSOCKET_STATE.get_path::<T>() else {
+            return Ok(BroadcastReport::default());
+        };
 
-            do_broadcast(None, Some(&path), Some(&topic), event, data, None).await?;
-        }
-        Ok(())
+        let topic: Topic = topic.into();
+
+        do_broadcast(audience, Some(&path), Some(&topic), event, data, None).await
     }
 
-    pub async fn broadcast_from(
-        user_id: &str,
+    /// Broadcast to every topic of the endpoint with subscribers on this node that
+    /// matches `pattern`, as channel patterns match topics, e.g. `"tenant:7:*"`.
+    ///
+    /// Each message carries the topic it was delivered on, and the report adds up
+    /// all of them.
+    pub async fn broadcast_matching(
+        pattern: &str,
+        event: &str,
+        data: Result<Value>,
+    ) -> Result<BroadcastReport> {
+        let Some(path) = WEBSOCKET_STATE.get_path::<T>() else {
+            return Ok(BroadcastReport::default());
+        };
+
+        let mut router = TopicRouter::default();
+        router.insert(pattern, ())?;
+
+        let mut topics = WEBSOCKET_STATE
+            .get_topics(&path)
+            .into_iter()
+            .filter(|topic| router.at(topic).is_some())
+            .collect::<Vec<_>>();
+        topics.sort();
+
+        do_broadcast_many(Audience::default(), &path, &topics, event, data).await
+    }
+
+    /// Broadcast to each of `topics`, with the topic of every message set to the
+    /// one it was delivered on. A topic listed twice is only sent to once.
+    pub async fn broadcast_many(
+        topics: &[&str],
+        event: &str,
+        data: Result<Value>,
+    ) -> Result<BroadcastReport> {
+        let Some(path) = WEBSOCKET_STATE.get_path::<T>() else {
+            return Ok(BroadcastReport::default());
+        };
+
+        let topics = topics.iter().map(|&topic| topic.into()).collect::<Vec<_>>();
+
+        do_broadcast_many(Audience::default(), &path, &topics, event, data).await
+    }
+
+    /// Push an event to every live connection of the endpoint whose socket id is
+    /// `socket_id`, from anywhere, returning whether any of them got it.
+    ///
+    /// The message is addressed to `topic` so clients hand it to that channel,
+    /// but is delivered whether or not the connection joined it, see
+    /// [`WebSocket::push_to_user_if_joined`].
+    pub async fn push_to_user(
+        socket_id: &str,
         topic: &str,
         event: &str,
         data: Result<Value>,
-    ) -> Result<()> {
-        if let Some(path) = WEBSOCKET_STATE.get_path::<T>() {
-            let topic: Topic = topic.into();
+    ) -> Result<bool> {
+        Self::push(socket_id, topic, event, data, false).await
+    }
 
-            do_broadcast(Some(user_id), Some(&path), Some(&topic), event, data, None).await?;
+    /// Like [`WebSocket::push_to_user`], skipping the connections that have not
+    /// joined `topic`.
+    pub async fn push_to_user_if_joined(
+        socket_id: &str,
+        topic: &str,
+        event: &str,
+        data: Result<Value>,
+    ) -> Result<bool> {
+        Self::push(socket_id, topic, event, data, true).await
+    }
+
+    /// Hand `message` to the `info` callback of channel `topic` on every connection
+    /// of the endpoint with socket id `socket_id`, returning how many got it.
+    ///
+    /// The callbacks are awaited in turn, so this must not be called from the
+    /// callbacks of a channel it reaches.
+    pub async fn send_info(socket_id: &str, topic: &str, message: Value) -> usize {
+        let Some(path) = WEBSOCKET_STATE.get_path::<T>() else {
+            return 0;
+        };
+
+        let topic: Topic = topic.into();
+        let mut sent = 0;
+
+        for key in WEBSOCKET_STATE.get_connections_on(&path, socket_id) {
+            let Some((socket, channel)) = WEBSOCKET_STATE.get_channel(&key, &topic) else {
+                continue;
+            };
+
+            if let Some(info) = channel.info.as_ref() {
+                info.call(message.clone(), socket).await;
+                sent += 1;
+            }
         }
-        Ok(())
+
+        sent
+    }
+
+    async fn push(
+        socket_id: &str,
+        topic: &str,
+        event: &str,
+        data: Result<Value>,
+        joined: bool,
+    ) -> Result<bool> {
+        let Some(path) = WEBSOCKET_STATE.get_path::<T>() else {
+            return Ok(false);
+        };
+
+        let recipients = do_push(&path, socket_id, &topic.into(), event, data, joined).await?;
+
+        Ok(recipients > 0)
     }
 
     pub fn connect<F, Fut, Res>(mut self, connect: F) -> Self
@@ -289,6 +330,35 @@ where
         self
     }
 
+    /// Accept broadcasts over HTTP at `POST {path}/broadcast`, for services that
+    /// cannot call [`WebSocket::broadcast`] themselves.
+    ///
+    /// The body is `{"topic", "event", "payload", "exclude"}` JSON, where `exclude`
+    /// is an optional socket id or list of socket ids to skip, and the response is the
+    /// [`BroadcastReport`] of this node.
+    ///
+    /// Every request is first passed to `authorize`, and one answered with a client
+    /// or server error status is rejected with that response, as with `connect`.
+    /// Only then is the body parsed, a malformed one being answered with `422`, as is
+    /// one whose event is reserved by the protocol (`phx_*`, `heartbeat`) or holds a
+    /// line break.
+    pub fn publish<F, Fut, Res>(mut self, authorize: F) -> Self
+    where
+        F: Fn(HeaderMap) -> Fut + Clone + Send + Sync + 'static,
+        Fut: Future<Output = Res> + Send + 'static,
+        Res: axum::response::IntoResponse,
+    {
+        self.publish = Some(Box::new(AuthorizeWrapper::new(move |headers| {
+            let authorize = authorize.clone();
+
+            Box::pin(async move {
+                let res = authorize(headers).await;
+                res.into_response()
+            })
+        })) as Box<dyn Authorize + Send + Sync>);
+        self
+    }
+
     pub fn id<F, Fut>(mut self, id: F) -> Self
     where
         F: Fn(Socket) -> Fut + Send + Sync + 'static,
@@ -305,18 +375,72 @@ where
     T: Default + Send + Sync + 'static,
 {
     fn from(websocket: WebSocket<T>) -> Self {
-        Router::new()
-            .route(
-                &format!("{}/websocket", websocket.path),
-                get(WebSocket::<T>::upgrade),
-            )
-            .layer(Extension(Arc::new(websocket)))
+        let mut router = Router::new().route(
+            &format!("{}/websocket", websocket.path),
+            get(WebSocket::<T>::upgrade),
+        );
+
+        if websocket.longpoll.is_some() {
+            router = router.route(
+                &format!("{}/longpoll", websocket.path),
+                get(WebSocket::<T>::longpoll_get).post(WebSocket::<T>::longpoll_post),
+            );
+        }
+
+        if websocket.sse.is_some() {
+            router = router.route(
+                &format!("{}/sse", websocket.path),
+                get(WebSocket::<T>::sse_stream),
+            );
+        }
+
+        if websocket.publish.is_some() {
+            router = router.route(
+                &format!("{}/broadcast", websocket.path),
+                post(WebSocket::<T>::publish_handler),
+            );
+        }
+
+        if websocket.admin.is_some() {
+            router = router
+                .route(
+                    &format!("{}/admin", websocket.path),
+                    get(WebSocket::<T>::admin_summary),
+                )
+                .route(
+                    &format!("{}/admin/topics/:topic", websocket.path),
+                    get(WebSocket::<T>::admin_topic),
+                )
+                .route(
+                    &format!("{}/admin/sockets/:id", websocket.path),
+                    get(WebSocket::<T>::admin_socket),
+                )
+                .route(
+                    &format!("{}/admin/connections/:id", websocket.path),
+                    get(WebSocket::<T>::admin_connection),
+                );
+        }
+
+        #[cfg(feature = "metrics")]
+        if websocket.metrics {
+            router = router.route(
+                &format!("{}/metrics", websocket.path),
+                get(WebSocket::<T>::metrics_handler),
+            );
+        }
+
+        router.layer(Extension(Arc::new(websocket)))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::{
+        fixture::{connect, join},
+        ChannelTest,
+    };
+    use serde_json::json;
 
     #[tokio::test]
     async fn websocket_callback_should_work() {
@@ -333,12 +457,140 @@ mod tests {
             .connect(test_connect)
             .id(test_id);
 
-        // let connect = websocket.connect.unwrap();
-        // let response = connect.call(json!({}), Socket::default()).await;
-        // assert_eq!(response, ());
+        let socket = connect(websocket).await.socket();
+
+        let socket = socket.lock().await;
+        assert_eq!(socket.assigns.get::<i32>("test"), Some(&1));
+        assert_eq!(socket.id, "test");
+    }
+
+    #[tokio::test]
+    async fn push_to_user_should_reach_every_connection_of_the_user() {
+        #[derive(Default)]
+        struct Endpoint;
+
+        async fn connect(params: Value, socket: Socket) {
+            let user = params["user"].as_str().unwrap_or_default().to_string();
+            socket.lock().await.assigns.insert("user", user);
+        }
+
+        async fn id(socket: Socket) -> Option<String> {
+            socket.lock().await.assigns.get::<String>("user").cloned()
+        }
+
+        let websocket = WebSocket::<Endpoint>::new("/push_to_user")
+            .connect(connect)
+            .id(id)
+            .channel("notifications:*", Channel::new().join(join));
+        let test = ChannelTest::new(websocket);
+
+        let phone = test.connect(json!({"user": "alice"})).await.unwrap();
+        let laptop = test.connect(json!({"user": "alice"})).await.unwrap();
+        let _bob = test.connect(json!({"user": "bob"})).await.unwrap();
+
+        let topic = "notifications:1";
+        let (_, phone) = phone.subscribe_and_join(topic, json!({})).await.unwrap();
+        let (_, laptop) = laptop.subscribe_and_join(topic, json!({})).await.unwrap();
+
+        let pushed = WebSocket::<Endpoint>::push_to_user("alice", topic, "ping", Ok(json!({})));
+        assert!(pushed.await.unwrap());
+        phone.assert_push("ping");
+        laptop.assert_push("ping");
+
+        let pushed =
+            WebSocket::<Endpoint>::push_to_user_if_joined("bob", topic, "ping", Ok(json!({})));
+        assert!(!pushed.await.unwrap());
+        let pushed = WebSocket::<Endpoint>::push_to_user("bob", topic, "ping", Ok(json!({})));
+        assert!(pushed.await.unwrap());
+        let pushed = WebSocket::<Endpoint>::push_to_user("carol", topic, "ping", Ok(json!({})));
+        assert!(!pushed.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn broadcast_should_honor_filter_and_exclusions() {
+        #[derive(Default)]
+        struct Endpoint;
+
+        async fn connect(params: Value, socket: Socket) {
+            let mut socket = socket.lock().await;
+            let user = params["user"].as_str().unwrap_or_default().to_string();
+            socket
+                .assigns
+                .insert("moderator", params["moderator"] == true);
+            socket.assigns.insert("user", user);
+        }
+
+        async fn id(socket: Socket) -> Option<String> {
+            socket.lock().await.assigns.get::<String>("user").cloned()
+        }
+
+        let websocket = WebSocket::<Endpoint>::new("/broadcast_audience")
+            .connect(connect)
+            .id(id)
+            .channel("room:*", Channel::new().join(join));
+        let test = ChannelTest::new(websocket);
+
+        let topic = "room:lobby";
+        let mut channels = vec![];
+        for (user, moderator) in [("alice", true), ("bob", false), ("carol", true)] {
+            let socket = test
+                .connect(json!({"user": user, "moderator": moderator}))
+                .await
+                .unwrap();
+            let (_, channel) = socket.subscribe_and_join(topic, json!({})).await.unwrap();
+            channels.push(channel);
+        }
+
+        let report =
+            WebSocket::<Endpoint>::broadcast_filter(topic, "flagged", Ok(json!({})), |s| {
+                s.assigns.get::<bool>("moderator") == Some(&true)
+            })
+            .await
+            .unwrap();
+        assert_eq!(report.delivered, 2);
+        channels[0].assert_push("flagged");
+        channels[1].refute_push("flagged");
+        channels[2].assert_push("flagged");
+
+        WebSocket::<Endpoint>::broadcast_except(&["alice", "carol"], topic, "hi", Ok(json!({})))
+            .await
+            .unwrap();
+        channels[0].refute_push("hi");
+        channels[1].assert_push("hi");
+        channels[2].refute_push("hi");
+    }
+
+    #[tokio::test]
+    async fn broadcast_should_reach_every_matching_topic_once() {
+        #[derive(Default)]
+        struct Endpoint;
+
+        let websocket = WebSocket::<Endpoint>::new("/broadcast_many")
+            .channel("tenant:*", Channel::new().join(join));
+        let socket = connect(websocket).await;
+
+        let mut channels = vec![];
+        for topic in ["tenant:7:room:1", "tenant:7:room:2", "tenant:8:room:1"] {
+            let (_, channel) = socket.subscribe_and_join(topic, json!({})).await.unwrap();
+            channels.push(channel);
+        }
 
-        let id = websocket.id.unwrap();
-        let response = id.call(Socket::default()).await;
-        assert_eq!(response, Some("test".to_string()));
+        let report =
+            WebSocket::<Endpoint>::broadcast_matching("tenant:7:*", "notice", Ok(json!({})))
+                .await
+                .unwrap();
+        assert_eq!(report.targeted, 2);
+        channels[0].assert_push("notice");
+        channels[1].assert_push("notice");
+        channels[2].refute_push("notice");
+
+        let topics = ["tenant:7:room:1", "tenant:8:room:1", "tenant:7:room:1"];
+        let report = WebSocket::<Endpoint>::broadcast_many(&topics, "notice", Ok(json!({})))
+            .await
+            .unwrap();
+        assert_eq!(report.targeted, 2);
+        channels[0].assert_push("notice");
+        channels[1].refute_push("notice");
+        channels[2].assert_push("notice");
     }
 }
@@ -11,6 +11,15 @@ pub enum WebSocketError {
     #[error("app error: {0}")]
     AppError(#[from] axum::Error),
 
+    #[error("invalid topic pattern: {0}")]
+    InvalidTopicPattern(String),
+
+    #[error("topic pattern registered twice: {0}")]
+    TopicConflict(String),
+
+    #[error("connection closed")]
+    ConnectionClosed,
+
     #[error("app error: {0}")]
     AnyhowError(#[from] anyhow::Error),
 }
@@ -1,9 +1,31 @@
-use crate::{handler::IntoResponse, message::Message, topic::Topic, user_id::UserId};
+use crate::{
+    broadcast::{Audience, BroadcastReport},
+    channel::Channel,
+    handler::IntoResponse,
+    message::Message,
+    outbound::{Offer, OutboundSender},
+    serializer::{FrameCache, Serializer},
+    shutdown::Shutdown,
+    socket,
+    sse::History,
+    topic::Topic,
+    user_id::UserId,
+    Socket,
+};
 use anyhow::Result;
-use dashmap::DashMap;
+#[cfg(any(test, feature = "test-util"))]
+use dashmap::DashSet;
+use dashmap::{mapref::entry::Entry, DashMap};
 use serde_json::Value;
-use std::{any::TypeId, borrow::Borrow, collections::HashSet, hash::Hash};
-use tokio::sync::mpsc::Sender;
+use std::{
+    any::TypeId,
+    borrow::Borrow,
+    collections::HashSet,
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+use tokio::sync::mpsc;
 
 lazy_static::lazy_static!(
     pub(crate) static ref WEBSOCKET_STATE: WebSocketState = WebSocketState::default();
@@ -12,8 +34,46 @@ lazy_static::lazy_static!(
 #[derive(Default)]
 pub(crate) struct WebSocketState {
     path: DashMap<TypeId, String>,
-    sender: DashMap<UserId, Sender<Message>>,
+    sender: DashMap<UserId, OutboundSender>,
     users: DashMap<(String, Topic), HashSet<UserId>>,
+    topics: DashMap<UserId, HashSet<(String, Topic)>>,
+    history: DashMap<String, Arc<Mutex<History>>>,
+    shutdown: DashMap<String, Arc<Shutdown>>,
+    connections: DashMap<UserId, ConnectionMeta>,
+    socket_ids: DashMap<String, HashSet<UserId>>,
+    subscriptions: DashMap<(UserId, Topic), Subscription>,
+    /// Channels receiving the broadcasts on a topic they did not join, by topic.
+    relays: DashMap<(String, Topic), HashSet<(UserId, Topic)>>,
+    /// The topics relayed to each channel, for cleaning up on leave.
+    relayed: DashMap<(UserId, Topic), HashSet<(String, Topic)>>,
+    /// Broadcasts waiting to be relayed on each connection, see [`Self::enqueue_relay`].
+    relay_queues: DashMap<UserId, mpsc::UnboundedSender<(Topic, Message)>>,
+    /// Test spies, getting every broadcast on their topics without being counted
+    /// or filtered like subscribers.
+    #[cfg(any(test, feature = "test-util"))]
+    spies: DashSet<UserId>,
+    /// Socket ids refused from joining a topic.
+    bans: DashMap<(String, Topic), HashSet<String>>,
+}
+
+/// The channel socket behind a subscription, for broadcast predicates, relays
+/// and kicks.
+pub(crate) struct Subscription {
+    pub(crate) socket: Socket,
+    /// Copy taken on join, read while a handler holds the socket's lock so that
+    /// broadcasts never wait on it.
+    pub(crate) snapshot: socket::Socket,
+    /// The joined channel, `None` for SSE streams.
+    pub(crate) channel: Option<Arc<Channel>>,
+}
+
+/// What is known about a connection besides its subscriptions.
+#[derive(Clone)]
+pub(crate) struct ConnectionMeta {
+    pub(crate) socket_id: String,
+    pub(crate) path: String,
+    pub(crate) serializer: Serializer,
+    pub(crate) connected_at: SystemTime,
 }
 
 impl WebSocketState {
@@ -33,14 +93,52 @@ impl WebSocketState {
         self.path.get(&type_id).map(|path| path.value().clone())
     }
 
-    pub fn insert_sender<K>(&self, key: K, val: Sender<Message>)
+    /// Start numbering and recording the broadcasts of a path, for SSE replay.
+    pub fn insert_history(&self, path: impl Into<String>, history: History) {
+        self.history
+            .insert(path.into(), Arc::new(Mutex::new(history)));
+    }
+
+    pub fn get_history(&self, path: &str) -> Option<Arc<Mutex<History>>> {
+        self.history
+            .get(path)
+            .map(|history| history.value().clone())
+    }
+
+    pub fn insert_shutdown(&self, path: impl Into<String>, shutdown: Shutdown) {
+        self.shutdown.insert(path.into(), Arc::new(shutdown));
+    }
+
+    pub fn get_shutdown(&self, path: &str) -> Option<Arc<Shutdown>> {
+        self.shutdown
+            .get(path)
+            .map(|shutdown| shutdown.value().clone())
+    }
+
+    pub fn insert_sender<K>(&self, key: K, val: OutboundSender)
     where
         K: Into<UserId>,
     {
         self.sender.insert(key.into(), val);
     }
 
-    pub fn get_sender<Q>(&self, key: &Q) -> Option<Sender<Message>>
+    /// Mark `entry` as a test spy, see `TestChannel`.
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn insert_spy(&self, entry: UserId) {
+        self.spies.insert(entry);
+    }
+
+    #[cfg(any(test, feature = "test-util"))]
+    fn is_spy(&self, entry: &UserId) -> bool {
+        self.spies.contains(entry)
+    }
+
+    #[cfg(not(any(test, feature = "test-util")))]
+    fn is_spy(&self, _entry: &UserId) -> bool {
+        false
+    }
+
+    pub fn get_sender<Q>(&self, key: &Q) -> Option<OutboundSender>
     where
         Q: ?Sized + Hash + Eq,
         UserId: Borrow<Q>,
@@ -48,7 +146,7 @@ impl WebSocketState {
         self.sender.get(key).map(|entry| entry.value().clone())
     }
 
-    pub fn remove_sender<Q>(&self, key: &Q) -> Option<Sender<Message>>
+    pub fn remove_sender<Q>(&self, key: &Q) -> Option<OutboundSender>
     where
         Q: ?Sized + Hash + Eq,
         UserId: Borrow<Q>,
@@ -56,41 +154,404 @@ impl WebSocketState {
         self.sender.remove(key).map(|(_, sender)| sender)
     }
 
+    /// Frames waiting in every outbound queue.
+    #[cfg(feature = "metrics")]
+    pub fn queued(&self) -> usize {
+        self.sender.iter().map(|tx| tx.value().len()).sum()
+    }
+
+    pub fn insert_connection(
+        &self,
+        key: UserId,
+        socket_id: impl Into<String>,
+        path: impl Into<String>,
+        serializer: Serializer,
+    ) {
+        let socket_id = socket_id.into();
+
+        self.socket_ids
+            .entry(socket_id.clone())
+            .or_default()
+            .insert(key.clone());
+
+        let meta = ConnectionMeta {
+            socket_id,
+            path: path.into(),
+            serializer,
+            connected_at: SystemTime::now(),
+        };
+
+        self.connections.insert(key, meta);
+    }
+
+    pub fn get_connection(&self, key: &str) -> Option<ConnectionMeta> {
+        self.connections.get(key).map(|meta| meta.value().clone())
+    }
+
+    /// Connections registered under a socket id.
+    pub fn get_connections_of(&self, socket_id: &str) -> HashSet<UserId> {
+        self.socket_ids
+            .get(socket_id)
+            .map(|keys| keys.value().clone())
+            .unwrap_or_default()
+    }
+
+    /// Connections with socket id `socket_id` to the endpoint at `path`.
+    pub fn get_connections_on(&self, path: &str, socket_id: &str) -> Vec<UserId> {
+        self.get_connections_of(socket_id)
+            .into_iter()
+            .filter(|key| {
+                self.connections
+                    .get(key)
+                    .is_some_and(|meta| meta.path == path)
+            })
+            .collect()
+    }
+
+    pub fn count_connections(&self, path: &str) -> usize {
+        self.connections
+            .iter()
+            .filter(|meta| meta.value().path == path)
+            .count()
+    }
+
     pub fn insert_user(&self, key: (String, Topic), entry: UserId) {
+        self.topics
+            .entry(entry.clone())
+            .or_default()
+            .insert(key.clone());
         self.users.entry(key).or_default().insert(entry);
     }
 
-    pub fn get_users(&self, key: &(String, Topic)) -> Option<HashSet<UserId>> {
+    /// Topics of a path that currently have subscribers.
+    pub fn get_topics(&self, path: &str) -> Vec<Topic> {
+        self.users
+            .iter()
+            .filter(|entry| entry.key().0 == path)
+            .map(|entry| entry.key().1.clone())
+            .collect()
+    }
+
+    /// Subscribers of a topic that are client connections, leaving out internal
+    /// listeners such as the test harness' broadcast spies.
+    pub fn get_users(&self, key: &(String, Topic)) -> Vec<String> {
         self.users
             .get(key)
-            .map(|entry| entry.value().iter().cloned().collect())
+            .map(|users| {
+                users
+                    .iter()
+                    .filter_map(|user| self.connections.get(user))
+                    .map(|meta| meta.socket_id.clone())
+                    .collect::<HashSet<_>>()
+                    .into_iter()
+                    .collect()
+            })
+            .unwrap_or_default()
     }
 
-    pub fn remove_user(&self, key: &(String, Topic), entry: &UserId) -> bool {
-        if let Some(mut users) = self.users.get_mut(key) {
-            users.remove(entry)
-        } else {
-            false
+    /// Topics of a path a connection is subscribed to.
+    pub fn get_user_topics(&self, path: &str, entry: &UserId) -> HashSet<Topic> {
+        self.topics
+            .get(entry)
+            .map(|topics| {
+                topics
+                    .iter()
+                    .filter(|(p, _)| p == path)
+                    .map(|(_, topic)| topic.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Remember the channel socket of a joined topic until the subscription ends.
+    pub fn insert_subscription(
+        &self,
+        entry: UserId,
+        topic: Topic,
+        socket: Socket,
+        snapshot: socket::Socket,
+        channel: Option<Arc<Channel>>,
+    ) {
+        let subscription = Subscription {
+            socket,
+            snapshot,
+            channel,
+        };
+
+        self.subscriptions.insert((entry, topic), subscription);
+    }
+
+    /// Take the subscription of a joined topic, so that whoever ends it first, be
+    /// it a leave, a kick or the connection closing, runs `terminate` only once.
+    pub fn take_subscription(&self, entry: &UserId, topic: &Topic) -> Option<Subscription> {
+        self.subscriptions
+            .remove(&(entry.clone(), topic.clone()))
+            .map(|(_, subscription)| subscription)
+    }
+
+    /// The channel socket and channel of a joined topic.
+    pub fn get_channel(&self, entry: &UserId, topic: &Topic) -> Option<(Socket, Arc<Channel>)> {
+        let subscription = self.subscriptions.get(&(entry.clone(), topic.clone()))?;
+        let channel = subscription.channel.clone()?;
+
+        Some((subscription.socket.clone(), channel))
+    }
+
+    pub fn has_subscription(&self, entry: &UserId, topic: &Topic) -> bool {
+        self.subscriptions
+            .contains_key(&(entry.clone(), topic.clone()))
+    }
+
+    pub fn insert_ban(&self, key: (String, Topic), socket_id: impl Into<String>) {
+        self.bans.entry(key).or_default().insert(socket_id.into());
+    }
+
+    pub fn remove_ban(&self, key: &(String, Topic), socket_id: &str) -> bool {
+        match self.bans.entry(key.clone()) {
+            Entry::Occupied(mut bans) => {
+                let removed = bans.get_mut().remove(socket_id);
+
+                if bans.get().is_empty() {
+                    bans.remove();
+                }
+
+                removed
+            }
+            Entry::Vacant(_) => false,
+        }
+    }
+
+    pub fn is_banned(&self, key: &(String, Topic), socket_id: &str) -> bool {
+        self.bans
+            .get(key)
+            .is_some_and(|bans| bans.contains(socket_id))
+    }
+
+    /// Relay the broadcasts on `key` to the channel `owner` of connection `entry`.
+    pub fn insert_relay(&self, key: (String, Topic), entry: UserId, owner: Topic) {
+        self.relay_queues
+            .entry(entry.clone())
+            .or_insert_with(|| spawn_relay_queue(entry.clone()));
+        self.relayed
+            .entry((entry.clone(), owner.clone()))
+            .or_default()
+            .insert(key.clone());
+        self.relays.entry(key).or_default().insert((entry, owner));
+    }
+
+    pub fn remove_relay(&self, key: &(String, Topic), entry: &UserId, owner: &Topic) -> bool {
+        let relay = (entry.clone(), owner.clone());
+
+        self.relayed.remove_if_mut(&relay, |_, keys| {
+            keys.remove(key);
+            keys.is_empty()
+        });
+
+        match self.relays.entry(key.clone()) {
+            Entry::Occupied(mut relays) => {
+                let removed = relays.get_mut().remove(&relay);
+
+                if relays.get().is_empty() {
+                    relays.remove();
+                }
+
+                removed
+            }
+            Entry::Vacant(_) => false,
+        }
+    }
+
+    /// Stop relaying any topic to the channel `owner` of connection `entry`.
+    pub fn remove_relays(&self, entry: &UserId, owner: &Topic) {
+        if let Some((_, keys)) = self.relayed.remove(&(entry.clone(), owner.clone())) {
+            for key in keys.iter() {
+                self.remove_relay(key, entry, owner);
+            }
+        }
+    }
+
+    /// Visit the channels relaying a topic whose connection still has a live sender.
+    fn for_each_relay<F>(&self, key: &(String, Topic), mut f: F)
+    where
+        F: FnMut(&UserId, &Topic),
+    {
+        if let Some(relays) = self.relays.get(key) {
+            for (user, owner) in relays.value() {
+                if self.sender.contains_key(user) {
+                    f(user, owner);
+                }
+            }
+        }
+    }
+
+    /// Hand a broadcast on another topic to the relay queue of connection `entry`,
+    /// so that the broadcaster never waits on the channel's `intercept` callback.
+    fn enqueue_relay(&self, entry: &UserId, owner: &Topic, message: &Message) -> Offer {
+        let Some(queue) = self.relay_queues.get(entry) else {
+            return Offer::Closed;
+        };
+
+        match queue.send((owner.clone(), message.clone())) {
+            Ok(()) => Offer::Queued,
+            Err(_) => Offer::Closed,
+        }
+    }
+
+    /// Push a broadcast on another topic to the channel `owner` of connection
+    /// `entry`, through the channel's `intercept` callback when it has one.
+    ///
+    /// Returns `None` when the channel is gone or the callback skipped it.
+    async fn relay(&self, entry: &UserId, owner: &Topic, message: &Message) -> Option<Offer> {
+        let tx = self.sender.get(entry)?.clone();
+        let (socket, join_ref, channel) = {
+            let subscription = self.subscriptions.get(&(entry.clone(), owner.clone()))?;
+            let join_ref = subscription
+                .snapshot
+                .message
+                .as_ref()
+                .and_then(|m| m.join_ref.clone());
+
+            (
+                subscription.socket.clone(),
+                join_ref,
+                subscription.channel.clone(),
+            )
+        };
+
+        let intercept = channel
+            .as_ref()
+            .and_then(|channel| channel.intercept.as_ref());
+        let payload = match intercept {
+            Some(intercept) => intercept
+                .call(
+                    message.topic.clone(),
+                    message.event.to_string(),
+                    message.payload.clone(),
+                    socket,
+                )
+                .await?
+                .into(),
+            None => message.payload.clone(),
+        };
+
+        let message = Message {
+            join_ref,
+            message_ref: None,
+            topic: owner.clone(),
+            event: message.event.clone(),
+            payload,
+        };
+
+        Some(tx.send_broadcast(tx.serializer().encode(&message)).await)
+    }
+
+    /// Whether a subscriber of `topic` passes the audience's predicate.
+    ///
+    /// The socket is read without waiting: the sender's own comes from the
+    /// audience, and one locked by a running handler is judged by its copy from
+    /// join time. Subscribers without a channel socket only pass when there is
+    /// no predicate.
+    fn accepts(&self, entry: &UserId, topic: &Topic, audience: &Audience) -> bool {
+        let Some(predicate) = audience.predicate else {
+            return true;
+        };
+
+        if let Some(sender) = audience.sender.filter(|sender| {
+            sender.connection_id == entry.as_str() && sender.topic.as_ref() == Some(topic)
+        }) {
+            return predicate(sender);
         }
+
+        let Some(subscription) = self.subscriptions.get(&(entry.clone(), topic.clone())) else {
+            return false;
+        };
+
+        let accepted = match subscription.socket.try_lock() {
+            Ok(socket) => predicate(&socket),
+            Err(_) => predicate(&subscription.snapshot),
+        };
+
+        accepted
+    }
+
+    /// Visit the subscribers of a topic that still have a live sender.
+    ///
+    /// The subscriber set is borrowed in place rather than copied, so `f` must
+    /// not block or touch `users` for the same topic.
+    pub fn for_each_user<F>(&self, key: &(String, Topic), mut f: F)
+    where
+        F: FnMut(&UserId, &OutboundSender),
+    {
+        if let Some(users) = self.users.get(key) {
+            for user in users.value() {
+                if let Some(tx) = self.sender.get(user) {
+                    f(user, tx.value());
+                }
+            }
+        }
+    }
+
+    pub fn remove_user(&self, key: &(String, Topic), entry: &UserId) -> bool {
+        self.topics.remove_if_mut(entry, |_, topics| {
+            topics.remove(key);
+            topics.is_empty()
+        });
+
+        self.unsubscribe(key, entry)
     }
 
     pub fn clearn_user(&self, entry: &UserId) {
         self.remove_sender(entry);
+        self.relay_queues.remove(entry);
+        #[cfg(any(test, feature = "test-util"))]
+        self.spies.remove(entry);
 
-        for mut users in self.users.iter_mut() {
-            users.value_mut().remove(entry);
+        if let Some((_, meta)) = self.connections.remove(entry) {
+            self.socket_ids.remove_if_mut(&meta.socket_id, |_, keys| {
+                keys.remove(entry);
+                keys.is_empty()
+            });
+        }
+
+        if let Some((_, topics)) = self.topics.remove(entry) {
+            for key in topics.iter() {
+                self.unsubscribe(key, entry);
+            }
+        }
+    }
+
+    /// Drop `entry` from a topic, removing the topic entirely once nobody is left.
+    ///
+    /// The emptiness check runs under the same shard lock as the removal, so a
+    /// concurrent `insert_user` either lands before it and keeps the entry alive
+    /// or after it and recreates the entry.
+    fn unsubscribe(&self, key: &(String, Topic), entry: &UserId) -> bool {
+        self.subscriptions.remove(&(entry.clone(), key.1.clone()));
+        self.remove_relays(entry, &key.1);
+
+        match self.users.entry(key.clone()) {
+            Entry::Occupied(mut users) => {
+                let removed = users.get_mut().remove(entry);
+
+                if users.get().is_empty() {
+                    users.remove();
+                }
+
+                removed
+            }
+            Entry::Vacant(_) => false,
         }
     }
 }
 
 pub(crate) async fn do_broadcast(
-    exclude_user: Option<&str>,
+    audience: Audience<'_>,
     path: Option<&String>,
     topic: Option<&Topic>,
     event: &str,
     data: Result<Value>,
     prev_message: Option<&Message>,
-) -> Result<()> {
+) -> Result<BroadcastReport> {
     let response = data.into_response();
     let payload: Value = response.into();
     let mut message = Message::builder()
@@ -104,19 +565,169 @@ pub(crate) async fn do_broadcast(
         message.merge(m);
     }
 
-    if let (Some(path), Some(topic)) = (path, topic) {
-        if let Some(users) = WEBSOCKET_STATE.get_users(&(path.clone(), topic.clone())) {
-            for user in users.iter() {
-                if exclude_user.map_or(true, |id| user.as_str() != id) {
-                    if let Some(tx) = WEBSOCKET_STATE.get_sender(user) {
-                        tx.send(message.clone()).await?;
-                    }
-                }
+    match (path, topic) {
+        (Some(path), Some(topic)) => Ok(fan_out(&audience, path, topic, &message).await),
+        _ => Ok(BroadcastReport::default()),
+    }
+}
+
+/// Broadcast to each of `topics` in turn, with the `topic` of every message set
+/// to the one it was delivered on. A topic listed twice is only sent to once.
+pub(crate) async fn do_broadcast_many(
+    audience: Audience<'_>,
+    path: &String,
+    topics: &[Topic],
+    event: &str,
+    data: Result<Value>,
+) -> Result<BroadcastReport> {
+    let response = data.into_response();
+    let payload: Value = response.into();
+    let mut message = Message::builder()
+        .event(event)
+        .payload(payload)
+        .build()
+        .unwrap();
+    let mut seen = HashSet::new();
+    let mut report = BroadcastReport::default();
+
+    for topic in topics.iter().filter(|topic| seen.insert(*topic)) {
+        message.topic.clone_from(topic);
+        report.merge(fan_out(&audience, path, topic, &message).await);
+    }
+
+    Ok(report)
+}
+
+/// Start the task relaying broadcasts to the channels of connection `entry`, in
+/// order. It ends once the connection is cleaned up and its queue drained.
+fn spawn_relay_queue(entry: UserId) -> mpsc::UnboundedSender<(Topic, Message)> {
+    let (tx, mut rx) = mpsc::unbounded_channel::<(Topic, Message)>();
+
+    tokio::spawn(async move {
+        while let Some((owner, message)) = rx.recv().await {
+            WEBSOCKET_STATE.relay(&entry, &owner, &message).await;
+        }
+    });
+
+    tx
+}
+
+/// Deliver `message` to the audience among the subscribers of `(path, topic)`.
+async fn fan_out(
+    audience: &Audience<'_>,
+    path: &String,
+    topic: &Topic,
+    message: &Message,
+) -> BroadcastReport {
+    let event = message.event.to_string();
+    let mut report = BroadcastReport::default();
+    let mut blocked = vec![];
+    let excluded = audience
+        .exclude
+        .iter()
+        .flat_map(|id| WEBSOCKET_STATE.get_connections_of(id))
+        .collect::<HashSet<_>>();
+
+    // The history lock only covers numbering and recording, and the broadcast is
+    // recorded before the fan-out so streams opened meanwhile can replay it.
+    let history = WEBSOCKET_STATE.get_history(path);
+    let id = history
+        .as_ref()
+        .and_then(|history| history.lock().unwrap().next_id());
+    let mut frames = FrameCache::new(message).with_id(id);
+
+    if let (Some(history), Some(id)) = (history, id) {
+        let frame = frames.get(Serializer::Sse);
+        history.lock().unwrap().push(id, topic.clone(), frame);
+    }
+
+    WEBSOCKET_STATE.for_each_user(&(path.clone(), topic.clone()), |user, tx| {
+        if WEBSOCKET_STATE.is_spy(user) {
+            tx.offer(frames.get(tx.serializer()));
+        } else if !excluded.contains(user) && WEBSOCKET_STATE.accepts(user, topic, audience) {
+            report.targeted += 1;
+
+            match tx.offer(frames.get(tx.serializer())) {
+                (Offer::Full, Some(frame)) => blocked.push((tx.clone(), frame)),
+                (offer, _) => report.record(offer),
             }
         }
+    });
+
+    // Relays only get queued here, the owning connection runs `intercept` itself.
+    WEBSOCKET_STATE.for_each_relay(&(path.clone(), topic.clone()), |user, owner| {
+        if !excluded.contains(user) && WEBSOCKET_STATE.accepts(user, owner, audience) {
+            report.targeted += 1;
+            report.record(WEBSOCKET_STATE.enqueue_relay(user, owner, message));
+        }
+    });
+
+    // Only subscribers with a full queue under `OverflowPolicy::Block` end up here,
+    // everyone else already has the message.
+    let sends = blocked
+        .into_iter()
+        .map(|(tx, frame)| async move { tx.send_broadcast(frame).await });
+
+    for offer in futures::future::join_all(sends).await {
+        report.record(offer);
     }
 
-    Ok(())
+    tracing::debug!(
+        path = %path,
+        topic = %&**topic,
+        event,
+        targeted = report.targeted,
+        delivered = report.delivered,
+        dropped = report.dropped,
+        failed = report.failed,
+        "broadcast"
+    );
+
+    #[cfg(feature = "metrics")]
+    {
+        crate::metrics::METRICS.fanout(path, report.delivered);
+        crate::metrics::METRICS.messages_out(&event, report.delivered);
+    }
+
+    report
+}
+
+/// Push to the connections of `path` with socket id `socket_id`, only those that
+/// joined `topic` when `joined` is set, returning how many got the message.
+pub(crate) async fn do_push(
+    path: &str,
+    socket_id: &str,
+    topic: &Topic,
+    event: &str,
+    data: Result<Value>,
+    joined: bool,
+) -> Result<usize> {
+    let response = data.into_response();
+    let payload: Value = response.into();
+    let message = Message::builder()
+        .topic(topic.clone())
+        .event(event)
+        .payload(payload)
+        .build()
+        .unwrap();
+
+    let mut recipients = 0;
+
+    for key in WEBSOCKET_STATE.get_connections_on(path, socket_id) {
+        if joined && !WEBSOCKET_STATE.get_user_topics(path, &key).contains(topic) {
+            continue;
+        }
+
+        if let Some(tx) = WEBSOCKET_STATE.get_sender(&key) {
+            if tx.send(&message).await.is_ok_and(|offer| offer.is_queued()) {
+                recipients += 1;
+            }
+        }
+    }
+
+    tracing::debug!(path, socket_id, topic = %&**topic, event, recipients, "push");
+
+    Ok(recipients)
 }
 
 #[cfg(test)]
@@ -134,4 +745,139 @@ mod tests {
             "/socket".to_string()
         );
     }
+
+    #[test]
+    fn websocket_state_should_remove_empty_topics() {
+        let state = WebSocketState::default();
+        let lobby = ("/socket".to_string(), Topic::from("room:lobby"));
+        let other = ("/socket".to_string(), Topic::from("room:other"));
+
+        state.insert_user(lobby.clone(), "user1".into());
+        state.insert_user(lobby.clone(), "user2".into());
+        state.insert_user(other.clone(), "user1".into());
+
+        assert!(state.remove_user(&lobby, &"user1".into()));
+        assert!(state.users.contains_key(&lobby));
+        assert_eq!(state.topics.get("user1").unwrap().len(), 1);
+
+        assert!(state.remove_user(&lobby, &"user2".into()));
+        assert!(!state.users.contains_key(&lobby));
+        assert!(!state.remove_user(&lobby, &"user2".into()));
+
+        state.clearn_user(&"user1".into());
+        assert!(state.users.is_empty());
+        assert!(state.topics.is_empty());
+    }
+
+    #[tokio::test]
+    async fn broadcast_should_report_every_subscriber() {
+        use crate::outbound::{self, OverflowPolicy, DEFAULT_BLOCK_TIMEOUT};
+
+        let path = "/report".to_string();
+        let topic = Topic::from("room:report");
+        let policies = [
+            ("report:ok", OverflowPolicy::Block),
+            ("report:full", OverflowPolicy::DropNewest),
+            ("report:slow", OverflowPolicy::Disconnect),
+            ("report:closed", OverflowPolicy::Block),
+        ];
+        let mut receivers = vec![];
+
+        for (user_id, policy) in policies {
+            let (tx, rx) = outbound::channel(Serializer::V2, 1, policy, DEFAULT_BLOCK_TIMEOUT);
+
+            if user_id != "report:ok" {
+                tx.offer(Serializer::V2.encode(&Message::default()));
+            }
+
+            if user_id == "report:closed" {
+                tx.close();
+            }
+
+            WEBSOCKET_STATE.insert_sender(user_id, tx);
+            WEBSOCKET_STATE.insert_user((path.clone(), topic.clone()), user_id.into());
+            receivers.push(rx);
+        }
+
+        let report = do_broadcast(
+            Audience::default(),
+            Some(&path),
+            Some(&topic),
+            "msg",
+            Ok(Value::Null),
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            report,
+            BroadcastReport {
+                targeted: 4,
+                delivered: 1,
+                dropped: 2,
+                failed: 1,
+            }
+        );
+
+        for (user_id, _) in policies {
+            WEBSOCKET_STATE.clearn_user(&user_id.into());
+        }
+    }
+
+    #[tokio::test]
+    async fn broadcast_should_not_wait_forever_on_a_stalled_subscriber() {
+        use crate::outbound::{self, OverflowPolicy};
+        use std::time::Duration;
+
+        let path = "/stalled".to_string();
+        let topic = Topic::from("room:stalled");
+        let subscribers = [("stalled:ok", 2), ("stalled:full", 1)];
+        let mut receivers = vec![];
+
+        for (user_id, capacity) in subscribers {
+            let (tx, rx) = outbound::channel(
+                Serializer::V2,
+                capacity,
+                OverflowPolicy::Block,
+                Duration::from_millis(50),
+            );
+
+            if user_id == "stalled:full" {
+                tx.offer(Serializer::V2.encode(&Message::default()));
+            }
+
+            WEBSOCKET_STATE.insert_sender(user_id, tx);
+            WEBSOCKET_STATE.insert_user((path.clone(), topic.clone()), user_id.into());
+            // Never drained.
+            receivers.push(rx);
+        }
+
+        let broadcast = do_broadcast(
+            Audience::default(),
+            Some(&path),
+            Some(&topic),
+            "msg",
+            Ok(Value::Null),
+            None,
+        );
+        let report = tokio::time::timeout(Duration::from_secs(1), broadcast)
+            .await
+            .expect("broadcast hung on a stalled subscriber")
+            .unwrap();
+
+        assert_eq!(
+            report,
+            BroadcastReport {
+                targeted: 2,
+                delivered: 1,
+                dropped: 1,
+                failed: 0,
+            }
+        );
+
+        for (user_id, _) in subscribers {
+            WEBSOCKET_STATE.clearn_user(&user_id.into());
+        }
+    }
 }
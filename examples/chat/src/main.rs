@@ -14,8 +14,8 @@ struct UserSocket;
 async fn main() {
     tracing_subscriber::fmt::init();
 
-    let room_channel = Channel::new()
-        .join(room_join)
+    // 频道状态在 join 时创建，之后以 &mut RoomState 传给每个处理函数
+    let room_channel = Channel::with_state(room_join)
         .handler("test", handler_test)
         .handler("test2", handler_test2);
 
@@ -40,11 +40,18 @@ async fn main() {
     axum::serve(listener, app).await.unwrap();
 }
 
-async fn socket_connect(params: serde_json::Value, _socket: Socket) -> impl IntoResponse {
+async fn socket_connect(params: serde_json::Value, socket: Socket) -> impl IntoResponse {
     // 通过 url 参数传递 token，可以在这里进行 token 验证，如果验证失败可以返回错误信息，然后断开连接
     // 验证通过后可以将用户信息存储到 socket 的 assigns 中，方便后续使用
     println!("token: {:?}", params);
 
+    // connection_assigns 存储连接级数据，所有频道共享同一份，修改随处可见
+    // 而 socket.assigns 是频道级数据，每个频道加入时复制一份，之后的修改互不可见
+    socket
+        .lock()
+        .await
+        .with_connection_assigns(|assigns| assigns.insert::<i32>("user_id", 1));
+
     "ok"
 }
 
@@ -62,41 +69,54 @@ struct User {
     name: String,
 }
 
+// 每个加入的房间各有一份状态，类型在编译期确定
+struct RoomState {
+    user: User,
+    messages: usize,
+}
+
 async fn room_join(
     topic: Topic,
     payload: Payload,
     socket: Socket,
-) -> anyhow::Result<serde_json::Value> {
+) -> anyhow::Result<(serde_json::Value, RoomState)> {
     // 加入房间时可以进行权限验证，如果验证失败可以返回错误信息，然后断开连接
     println!("room_join: {:?}, {:?}", topic, payload);
 
-    let mut socket = socket.lock().await;
-    // assigns 可以存储任意类型的数据
-    socket.assigns.insert::<i32>("user_id", 1);
-    socket.assigns.insert::<User>(
-        "user",
-        User {
-            id: 1,
+    let socket = socket.lock().await;
+    let Some(user_id) =
+        socket.with_connection_assigns(|assigns| assigns.get::<i32>("user_id").copied())
+    else {
+        anyhow::bail!("unauthorized");
+    };
+
+    let state = RoomState {
+        user: User {
+            id: user_id,
             name: "test".to_string(),
         },
-    );
+        messages: 0,
+    };
 
     // 返回错误信息将会导致连接失败，并返回错误信息给客户端，handler函数也会如此
     // Err(anyhow::anyhow!(json!({"reason": "auth failed"})))
 
     // 连接成功，信息将发送给客户端
-    Ok(json!({"user_id": 1}))
+    Ok((json!({"user_id": user_id}), state))
 }
 
-async fn handler_test(payload: Payload, socket: Socket) -> anyhow::Result<&'static str> {
+async fn handler_test(
+    payload: Payload,
+    state: &mut RoomState,
+    socket: Socket,
+) -> anyhow::Result<&'static str> {
     // 事件处理函数，可以在这里处理业务逻辑，然后返回结果
     println!("handler_test: {:?}", payload);
 
+    state.messages += 1;
+    println!("user: {:?}, messages: {}", state.user, state.messages);
+
     let socket = socket.lock().await;
-    let user_id = socket.assigns.get::<i32>("user_id").unwrap();
-    let user = socket.assigns.get::<User>("user").unwrap();
-    println!("user_id: {:?}", user_id);
-    println!("user: {:?}", user);
     // 主动推送事件给当前用户
     socket
         .push(
@@ -126,7 +146,11 @@ async fn handler_test(payload: Payload, socket: Socket) -> anyhow::Result<&'stat
     Ok("test")
 }
 
-async fn handler_test2(payload: Payload, _socket: Socket) -> anyhow::Result<()> {
+async fn handler_test2(
+    payload: Payload,
+    _state: &mut RoomState,
+    _socket: Socket,
+) -> anyhow::Result<()> {
     println!("handler_test2: {:?}", payload);
 
     // 广播事件给所有用户，可以在http业务逻辑中调用